@@ -1,11 +1,39 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
-use criterion::{criterion_group, criterion_main, Criterion};
+use criterion::{criterion_group, Criterion};
 
 use game_lib::actor::Direction;
-use game_lib::path::{self, astar, HeuristicModel, Model, Optimizer, Sampler};
+use game_lib::path::pool::PlannerPool;
+use game_lib::path::{self, astar, HeuristicModel, Model, Optimizer, PathResult, Sampler};
 use game_lib::Position;
 
+/// Counts every allocation, to check that reused planners do not allocate while searching
+struct Counting;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
 #[derive(Copy, Clone, Debug)]
 enum Tile {
     // A blocked tile, or Wall
@@ -92,6 +120,7 @@ impl<H: Heuristic> Model for BenchModel<H> {
     type Control = Direction;
     type State = Position;
     type Cost = usize;
+    type Goal = Position;
 
     fn cost(&self, _: &Self::State, action: &Self::Control, _: &Self::State) -> Self::Cost {
         use Direction::*;
@@ -263,7 +292,84 @@ fn single_iter(c: &mut Criterion) {
     });
 }
 
+fn pooled_octile(c: &mut Criterion) {
+    let mut map = map();
+    let start = Position::new(30, 12);
+    let goal = Position::new(0, 15);
+    let mut sampler = Octile;
+
+    let pool = PlannerPool::<astar::AStar<BenchModel<Diagonal>>>::astar(1, 1024);
+    c.bench_function("Pooled octile path", move |b| {
+        b.iter(|| {
+            let mut planner = pool.get();
+            planner.optimize(&mut map, &start, &goal, &mut sampler);
+        });
+    });
+}
+
+/// Repeat a query with a pooled planner, and count the allocations of each query once warm
+fn steady_allocations<O, H>(pool: &PlannerPool<O>, sampler: &mut Octile) -> f64
+where
+    O: Optimizer<BenchModel<H>, Octile>,
+    H: Heuristic,
+{
+    const WARM_UP: usize = 10;
+    const QUERIES: usize = 100;
+
+    let mut map = map::<H>();
+    let (start, goal) = (Position::new(30, 12), Position::new(0, 15));
+
+    let mut allocations = 0;
+    for query in 0..WARM_UP + QUERIES {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let result = pool.get().optimize(&mut map, &start, &goal, sampler);
+        let after = ALLOCATIONS.load(Ordering::Relaxed);
+
+        match result {
+            PathResult::Final(_) => {}
+            _ => panic!("the benchmark map has a path"),
+        }
+        if query >= WARM_UP {
+            allocations += after - before;
+        }
+    }
+
+    allocations as f64 / QUERIES as f64
+}
+
+/// Check that steady-state queries allocate nothing besides the trajectory they return
+///
+/// Run with `COUNT_ALLOCATIONS=1 cargo bench` instead of timing the benchmarks.
+fn count_allocations() {
+    let astar = PlannerPool::<astar::AStar<BenchModel<Diagonal>>>::astar(1, 1024);
+    let dijkstra =
+        PlannerPool::<path::dijkstra::Dijkstra<BenchModel<Zero>>>::dijkstra(1, 1024);
+
+    let counts = [
+        ("AStar", steady_allocations(&astar, &mut Octile)),
+        ("Dijkstra", steady_allocations(&dijkstra, &mut Octile)),
+    ];
+    for (name, count) in counts.iter() {
+        println!("{:<10} {:.2} allocations per query, with the trajectory", name, count);
+        assert!(*count <= 1.0, "{} allocates while searching", name);
+    }
+}
+
 criterion_group!(octile, full_octile, full_dijkstra_octile);
 criterion_group!(cardinal, full_cardinal, full_dijkstra_cardinal);
 criterion_group!(single_path, single_iter);
-criterion_main!(octile, cardinal, single_path);
+criterion_group!(reuse, pooled_octile);
+
+fn main() {
+    if env::var_os("COUNT_ALLOCATIONS").is_some() {
+        count_allocations();
+        return;
+    }
+
+    octile();
+    cardinal();
+    single_path();
+    reuse();
+
+    Criterion::default().configure_from_args().final_summary();
+}
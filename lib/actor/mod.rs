@@ -1,11 +1,33 @@
 use super::Position;
-use crate::map::Map;
-use crate::path::{self, HeuristicModel, Model, Optimizer, PathResult, Sampler, State};
+use crate::map::{Components, Connectivity, Map};
+use crate::nav::{FirstMoves, FlowField, Landmarks};
+use crate::path::spacetime::{Reservations, SpaceTime, Timed, TimedSampler};
+use crate::path::{
+    self, Budget, Diagnostics, Dominance, HeuristicModel, Model, Optimizer, PathFindingErr,
+    PathResult, Sampler, State, Target,
+};
 
 use std::fmt::{self, Display};
+use std::sync::Arc;
 
 pub type ActionResult = Result<(), String>;
 
+/// Mana consumed by a single teleport
+pub const TELEPORT_COST: usize = 5;
+
+/// Tiles crossed by a teleport along one axis
+pub const TELEPORT_DISTANCE: u32 = 10;
+
+/// Tiles crossed along both axes by a diagonal teleport
+pub const TELEPORT_DIAGONAL_DISTANCE: u32 = 5;
+
+/// Number of turns ahead an actor plans when avoiding other actors
+pub const SPACE_TIME_HORIZON: usize = 64;
+
+/// Number of states an actor expands when avoiding other actors, before settling for the
+/// closest state found
+pub const SPACE_TIME_EXPANSIONS: usize = 4096;
+
 pub trait Action {
     fn execute(&self, map: &Map, actor: &mut Actor) -> ActionResult;
 }
@@ -19,6 +41,19 @@ pub struct Actor {
 
 pub enum Goal {
     GoTo(Position),
+    /// Go to whichever of the positions is the cheapest to reach
+    GoToNearest(Vec<Position>),
+    /// Walk along a flow field shared with other actors, without planning
+    Follow(Arc<FlowField>),
+    /// Walk toward a position with the first moves of a database, without planning
+    Lookup(Arc<FirstMoves>, Position),
+    /// Go to a position without entering the positions other actors reserved, waiting for
+    /// them to pass when needed.  `turn` is the current turn of the reservation table.
+    GoToAround {
+        position: Position,
+        reservations: Arc<Reservations<Position>>,
+        turn: usize,
+    },
     Do(Box<dyn Action>),
     None,
 }
@@ -47,27 +82,73 @@ impl Actor {
         Actor { pos: Position { x, y }, mana, max_mana }
     }
 
-    pub fn take_turn(&mut self, goal: Goal, map: &Map) -> Box<dyn Action> {
-        let map = map.clone();
-
+    pub fn take_turn(&mut self, goal: Goal, map: &Arc<Map>) -> Box<dyn Action> {
         match goal {
             Goal::GoTo(position) => {
-                // Create a goal to go to the defined position
-                let mut goal = self.clone();
-                goal.pos = position;
+                // Stop next to the position, since it may be occupied by what we want to reach
+                let goal = Target::Within(position, 1);
                 let mut planner = path::astar::AStar::new();
+                // Still get closer when the position is enclosed
+                planner.set_budget(Budget::unlimited().best_effort_heuristic());
                 let mut walker = WalkSampler::new();
-                let mut model = TurnOptimal::new(map);
+                let mut model = TurnOptimal::new(Arc::clone(map));
                 let trajectory = planner.optimize(&mut model, self, &goal, &mut walker);
 
-                if let PathResult::Final(trajectory) = trajectory {
-                    if let Some((_, action)) = trajectory.trajectory.first() {
-                        Box::new(action.clone())
-                    } else {
-                        Box::new(Movement::None)
-                    }
-                } else {
-                    Box::new(Movement::None)
+                first_action(trajectory)
+            }
+            Goal::GoToNearest(positions) => {
+                let goals: Vec<_> =
+                    positions.into_iter().map(|p| Target::Within(p, 1)).collect();
+                let mut planner = path::astar::AStar::new();
+                planner.set_budget(Budget::unlimited().best_effort_heuristic());
+                let mut walker = WalkSampler::new();
+                let mut model = TurnOptimal::new(Arc::clone(map));
+                let trajectory =
+                    planner.optimize_nearest(&mut model, self, &goals, &mut walker);
+
+                first_action(trajectory)
+            }
+            Goal::GoToAround { position, reservations, turn } => {
+                let goal = Target::Within(position, 1);
+                let mut planner = path::astar::AStar::new();
+                // Every turn of the horizon is a new state, so a goal which cannot be reached
+                // in time would have the search expand every tile on every turn
+                planner.set_budget(
+                    Budget::unlimited()
+                        .expansions(SPACE_TIME_EXPANSIONS)
+                        .best_effort_heuristic(),
+                );
+                let mut walker = TimedSampler::new(WalkSampler::with_wait());
+                let mut turn_optimal = TurnOptimal::new(Arc::clone(map));
+                let mut model = SpaceTime::new(
+                    &mut turn_optimal,
+                    &reservations,
+                    turn + SPACE_TIME_HORIZON,
+                );
+                let start = Timed::new(self.clone(), turn);
+                let trajectory = planner.optimize(&mut model, &start, &goal, &mut walker);
+
+                first_action(trajectory)
+            }
+            Goal::Follow(flow) => {
+                // Stop next to the goal like `GoTo`, rather than walking onto it
+                if Target::Within(flow.goal().clone(), 1).contains(&self.pos) {
+                    return Box::new(Movement::None);
+                }
+
+                match flow.get(&self.pos) {
+                    Some(direction) => Box::new(Movement::Walk(direction)),
+                    None => Box::new(Movement::None),
+                }
+            }
+            Goal::Lookup(moves, position) => {
+                if Target::Within(position.clone(), 1).contains(&self.pos) {
+                    return Box::new(Movement::None);
+                }
+
+                match moves.get(&self.pos, &position) {
+                    Some(direction) => Box::new(Movement::Walk(direction)),
+                    None => Box::new(Movement::None),
                 }
             }
             Goal::Do(action) => action,
@@ -76,6 +157,24 @@ impl Actor {
     }
 }
 
+/// The first action of a finished or partial plan, or no movement when planning failed
+fn first_action<M>(result: PathResult<M>) -> Box<dyn Action>
+where
+    M: Model<Control = Movement>,
+{
+    let trajectory = match result {
+        PathResult::Final(trajectory) | PathResult::Partial(trajectory) => trajectory,
+        _ => return Box::new(Movement::None),
+    };
+
+    // The first step is the starting state, paired with `Movement::None` as no action
+    // reaches it, so the action to take is the one reaching the second state
+    match trajectory.trajectory.get(1) {
+        Some((_, action)) => Box::new(action.clone()),
+        None => Box::new(Movement::None),
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Direction {
     North,
@@ -109,6 +208,58 @@ impl fmt::Debug for Direction {
 }
 
 impl Direction {
+    /// Every direction, clockwise from north
+    pub const ALL: [Direction; 8] = [
+        Direction::North,
+        Direction::NorthEast,
+        Direction::East,
+        Direction::SouthEast,
+        Direction::South,
+        Direction::SouthWest,
+        Direction::West,
+        Direction::NorthWest,
+    ];
+
+    /// The change in `x` and `y` of a step in this direction
+    pub fn offset(self) -> (i64, i64) {
+        use Direction::*;
+        match self {
+            North => (0, 1),
+            NorthEast => (1, 1),
+            East => (1, 0),
+            SouthEast => (1, -1),
+            South => (0, -1),
+            SouthWest => (-1, -1),
+            West => (-1, 0),
+            NorthWest => (-1, 1),
+        }
+    }
+
+    /// Test if the direction moves along both axes at once
+    pub fn is_diagonal(self) -> bool {
+        let (dx, dy) = self.offset();
+        dx != 0 && dy != 0
+    }
+
+    /// Step from a position, unless the step would leave the range of `u32`
+    pub fn checked_step(self, x: u32, y: u32) -> Option<(u32, u32)> {
+        let (dx, dy) = self.offset();
+        let (nx, ny) = (i64::from(x) + dx, i64::from(y) + dy);
+        if nx < 0 || ny < 0 || nx > i64::from(u32::MAX) || ny > i64::from(u32::MAX) {
+            None
+        } else {
+            Some((nx as u32, ny as u32))
+        }
+    }
+
+    /// The direction of a single step between two neighbouring positions
+    pub fn between(from: &Position, to: &Position) -> Option<Direction> {
+        Direction::ALL
+            .iter()
+            .cloned()
+            .find(|direction| direction.checked_step(from.x, from.y) == Some((to.x, to.y)))
+    }
+
     pub fn step_from(self, x: u32, y: u32) -> (u32, u32) {
         use Direction::*;
         match self {
@@ -134,6 +285,8 @@ impl Default for Direction {
 pub enum Movement {
     Teleport(Direction),
     Walk(Direction),
+    /// Stay in place for a turn, letting another actor pass
+    Wait,
     None,
 }
 
@@ -144,7 +297,8 @@ impl Default for Movement {
 }
 
 pub struct WalkSampler {
-    movements: [Movement; 8],
+    movements: [Movement; 9],
+    wait: bool,
 }
 
 impl WalkSampler {
@@ -162,9 +316,21 @@ impl WalkSampler {
                 Walk(SouthWest),
                 Walk(West),
                 Walk(NorthWest),
+                Wait,
             ],
+            wait: false,
         }
     }
+
+    /// Also sample waiting in place, which is only useful when planning through time
+    pub fn with_wait() -> Self {
+        WalkSampler { wait: true, ..Self::new() }
+    }
+
+    /// The moves sampled, for a heuristic which stays admissible
+    pub fn moves(&self) -> MoveSet {
+        MoveSet::walk()
+    }
 }
 
 impl Default for WalkSampler {
@@ -176,10 +342,15 @@ impl Default for WalkSampler {
 impl Sampler<TurnOptimal> for WalkSampler {
     #[inline]
     fn sample(&mut self, _: &TurnOptimal, _: &Actor) -> &[Movement] {
-        &self.movements
+        if self.wait {
+            &self.movements
+        } else {
+            &self.movements[..8]
+        }
     }
 }
 
+/// Sample walking in every direction, and teleporting when the actor has the mana for it
 pub struct TeleportSampler {
     movements: [Movement; 16],
 }
@@ -210,6 +381,11 @@ impl TeleportSampler {
             ],
         }
     }
+
+    /// The moves sampled, for a heuristic which stays admissible
+    pub fn moves(&self) -> MoveSet {
+        MoveSet::teleport()
+    }
 }
 
 impl Default for TeleportSampler {
@@ -220,8 +396,12 @@ impl Default for TeleportSampler {
 
 impl Sampler<TurnOptimal> for TeleportSampler {
     #[inline]
-    fn sample(&mut self, _: &TurnOptimal, _: &Actor) -> &[Movement] {
-        &self.movements
+    fn sample(&mut self, _: &TurnOptimal, current: &Actor) -> &[Movement] {
+        if current.mana >= TELEPORT_COST {
+            &self.movements
+        } else {
+            &self.movements[..8]
+        }
     }
 }
 
@@ -231,7 +411,7 @@ impl Action for Movement {
 
         actor.mana = (actor.mana + 1).min(actor.max_mana);
         match self {
-            None => Ok(()),
+            None | Wait => Ok(()),
             Walk(direction) => {
                 let Position { x, y } = &actor.pos;
                 let (nx, ny) = direction.step_from(*x, *y);
@@ -251,8 +431,10 @@ impl Action for Movement {
                 let Position { mut x, mut y } = &actor.pos;
                 use Direction::*;
                 let distance = match direction {
-                    SouthEast | NorthEast | SouthWest | NorthWest => 5,
-                    _ => 10,
+                    SouthEast | NorthEast | SouthWest | NorthWest => {
+                        TELEPORT_DIAGONAL_DISTANCE
+                    }
+                    _ => TELEPORT_DISTANCE,
                 };
                 for _ in 0..distance {
                     let (nx, ny) = direction.step_from(x, y);
@@ -268,10 +450,10 @@ impl Action for Movement {
                     }
                 }
                 actor.pos = Position { x, y };
-                if actor.mana < 5 {
+                if actor.mana < TELEPORT_COST {
                     Err(format!("Consumes too much mana for {:?}", actor))
                 } else {
-                    actor.mana -= 5;
+                    actor.mana -= TELEPORT_COST;
                     Ok(())
                 }
             }
@@ -285,6 +467,124 @@ impl State for Actor {
     fn grid_position(&self) -> Self::Position {
         self.pos.clone()
     }
+
+    fn tile(&self) -> Option<Position> {
+        Some(self.pos.clone())
+    }
+}
+
+/// More mana at the same position leaves at least as many moves open
+impl Dominance for Actor {
+    fn dominates(&self, other: &Self) -> bool {
+        self.pos == other.pos && self.mana >= other.mana
+    }
+}
+
+/// The moves an actor can make, each with the displacement along both axes and its cost
+///
+/// Moves are symmetric: every move can be made in either direction along each axis, and
+/// with its axes swapped.  Estimating with a move set never over-estimates the cost of
+/// reaching a goal on an open map, so heuristics built on the move set of a sampler keep
+/// the search optimal, however far the moves jump.
+///
+/// ```
+/// use game_lib::actor::MoveSet;
+///
+/// // Walking is exactly the octile distance
+/// let walk = MoveSet::walk();
+/// assert_eq!(walk.estimate(4, 2), 2 * 2 + 3 * 2);
+///
+/// // Teleporting covers 10 tiles for the price of a single step
+/// let teleport = MoveSet::teleport();
+/// assert_eq!(teleport.estimate(20, 0), 4);
+/// assert!(teleport.estimate(4, 2) <= walk.estimate(4, 2));
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MoveSet {
+    moves: Vec<(u32, u32, usize)>,
+}
+
+impl MoveSet {
+    /// A move set without any move, which estimates every goal at `0`
+    pub fn new() -> Self {
+        MoveSet { moves: Vec::new() }
+    }
+
+    /// Walking one tile in eight directions, for the costs of `TurnOptimal`
+    pub fn walk() -> Self {
+        MoveSet::new().with(1, 0, 2).with(1, 1, 3)
+    }
+
+    /// Walking, and teleporting for the cost of a single step
+    pub fn teleport() -> Self {
+        MoveSet::walk().with(TELEPORT_DISTANCE, 0, 2).with(
+            TELEPORT_DIAGONAL_DISTANCE,
+            TELEPORT_DIAGONAL_DISTANCE,
+            2,
+        )
+    }
+
+    /// Add a move crossing `dx` and `dy` tiles for a cost, along with its mirror image
+    pub fn with(mut self, dx: u32, dy: u32, cost: usize) -> Self {
+        for &(dx, dy) in [(dx, dy), (dy, dx)].iter() {
+            if (dx, dy) != (0, 0) && !self.moves.contains(&(dx, dy, cost)) {
+                self.moves.push((dx, dy, cost));
+            }
+        }
+        self
+    }
+
+    /// The moves, with their displacements along both axes and their costs
+    pub fn moves(&self) -> &[(u32, u32, usize)] {
+        &self.moves
+    }
+
+    /// The lowest cost of any mix of moves which covers `dx` and `dy` tiles
+    ///
+    /// Moves are counted in fractions, which bounds any whole number of moves from below.
+    /// The cheapest mix uses at most two kinds of moves, so it is found by trying every move
+    /// alone and every pair of moves covering both axes exactly.
+    pub fn estimate(&self, dx: u32, dy: u32) -> usize {
+        if (dx, dy) == (0, 0) {
+            return 0;
+        }
+
+        let (dx, dy) = (f64::from(dx), f64::from(dy));
+        let count = |distance: f64, step: u32| {
+            if step > 0 {
+                distance / f64::from(step)
+            } else if distance > 0.0 {
+                f64::INFINITY
+            } else {
+                0.0
+            }
+        };
+
+        let mut best = f64::INFINITY;
+        for (i, &(ax, ay, a_cost)) in self.moves.iter().enumerate() {
+            best = best.min(count(dx, ax).max(count(dy, ay)) * a_cost as f64);
+
+            for &(bx, by, b_cost) in &self.moves[i + 1..] {
+                let (ax, ay, bx, by) =
+                    (f64::from(ax), f64::from(ay), f64::from(bx), f64::from(by));
+                let determinant = ax * by - ay * bx;
+                if determinant != 0.0 {
+                    let a = (dx * by - dy * bx) / determinant;
+                    let b = (ax * dy - ay * dx) / determinant;
+                    if a >= 0.0 && b >= 0.0 {
+                        best = best.min(a * a_cost as f64 + b * b_cost as f64);
+                    }
+                }
+            }
+        }
+
+        // Costs are whole numbers, so the bound rounds up, short of rounding errors
+        if best.is_finite() {
+            (best - 1e-6).ceil().max(0.0) as usize
+        } else {
+            0
+        }
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -292,7 +592,17 @@ pub enum Heuristic {
     Manhattan,
     Chebyshev,
     DoubleManhattan,
+    /// The octile distance, which is exact for walking on an open map
     Diagonal,
+    /// The straight-line distance in units of a straight step, for walking
+    Euclidean,
+    /// The cheapest mix of moves from a move set, which stays admissible with teleports
+    Moves(MoveSet),
+    /// The best of the diagonal distance and the landmark bound, which follows the walls
+    ///
+    /// The landmarks must be picked on the map of the model, and the bound only holds for
+    /// walking.
+    Landmarks(Arc<Landmarks>),
 }
 
 impl Heuristic {
@@ -307,6 +617,13 @@ impl Heuristic {
             DoubleManhattan => 4 * (dx + dy),
             Chebyshev => (dx + dy) - dx.min(dy),
             Diagonal => 2 * (dx + dy) - dx.min(dy),
+            Euclidean => (2.0 * ((dx * dx + dy * dy) as f64).sqrt()) as isize,
+            Moves(moves) => moves.estimate(dx as u32, dy as u32) as isize,
+            Landmarks(landmarks) => {
+                let from = Position::new(cx as u32, cy as u32);
+                let to = Position::new(gx as u32, gy as u32);
+                (2 * (dx + dy) - dx.min(dy)).max(landmarks.distance(&from, &to) as isize)
+            }
         };
         estimate as usize
     }
@@ -319,6 +636,11 @@ impl Display for Heuristic {
             Heuristic::DoubleManhattan => write!(f, "Doubled-Manhattan"),
             Heuristic::Chebyshev => write!(f, "Chebyshev"),
             Heuristic::Diagonal => write!(f, "Diagonal"),
+            Heuristic::Euclidean => write!(f, "Euclidean"),
+            Heuristic::Moves(_) => write!(f, "Move Set"),
+            Heuristic::Landmarks(landmarks) => {
+                write!(f, "Landmarks ({})", landmarks.positions().len())
+            }
         }
     }
 }
@@ -326,12 +648,26 @@ impl Display for Heuristic {
 #[derive(Clone, Debug)]
 pub struct TurnOptimal {
     heurisitc: Heuristic,
-    map: Map,
+    map: Arc<Map>,
+    components: Option<Arc<Components>>,
 }
 
 impl TurnOptimal {
-    pub fn new(map: Map) -> Self {
-        TurnOptimal { map, heurisitc: Heuristic::Manhattan }
+    /// Walk on a map, which is shared rather than copied when given as an `Arc`
+    pub fn new<T>(map: T) -> Self
+    where
+        T: Into<Arc<Map>>,
+    {
+        TurnOptimal { map: map.into(), heurisitc: Heuristic::Manhattan, components: None }
+    }
+
+    /// Reject goals outside of the component of the start before searching
+    ///
+    /// The components must be labeled with 8-connectivity from the same map as the model,
+    /// since walking and teleporting never leave a component.
+    pub fn set_components(&mut self, components: Arc<Components>) {
+        debug_assert_eq!(components.connectivity(), Connectivity::Eight);
+        self.components = Some(components)
     }
 
     pub fn set_heuristic(&mut self, heuristic: Heuristic) {
@@ -346,8 +682,57 @@ impl TurnOptimal {
         self.heurisitc = Heuristic::Manhattan
     }
 
+    pub fn map(&self) -> &Arc<Map> {
+        &self.map
+    }
+
+    /// Plan on another map, or on a newer revision of the same one
+    ///
+    /// The components are dropped when the revision changed, since they were labeled from
+    /// the previous map.  Optimizers which kept searching across calls must be cleared.
+    pub fn set_map<T>(&mut self, map: T)
+    where
+        T: Into<Arc<Map>>,
+    {
+        let map = map.into();
+        if map.revision() != self.map.revision() {
+            self.components = None;
+        }
+        self.map = map
+    }
+
+    /// The [`revision`] of the map planned on
+    ///
+    /// [`revision`]: ../map/struct.Map.html#method.revision
+    pub fn revision(&self) -> u64 {
+        self.map.revision()
+    }
+
+    /// Test if the model plans on the same revision of a map
+    ///
+    /// ```
+    /// # use game_lib::map::{Map, Tile};
+    /// use game_lib::actor::TurnOptimal;
+    /// use std::sync::Arc;
+    ///
+    /// let mut map = Arc::new(Map::new(8, 8));
+    /// let mut model = TurnOptimal::new(Arc::clone(&map));
+    /// assert!(model.is_current(&map));
+    ///
+    /// // The map is copied once, since the model still shares it
+    /// Arc::make_mut(&mut map)[(2, 2)] = Tile::FLOOR;
+    /// assert!(!model.is_current(&map));
+    ///
+    /// model.set_map(Arc::clone(&map));
+    /// assert!(model.is_current(&map));
+    /// ```
+    pub fn is_current(&self, map: &Map) -> bool {
+        self.map.revision() == map.revision()
+    }
+
+    /// Take the map back, copying it only when it is still shared
     pub fn return_map(self) -> Map {
-        self.map
+        Arc::try_unwrap(self.map).unwrap_or_else(|map| (*map).clone())
     }
 }
 
@@ -355,13 +740,22 @@ impl Model for TurnOptimal {
     type Control = Movement;
     type State = Actor;
     type Cost = usize;
+    type Goal = Target;
+
+    fn converge(&self, current: &Self::State, goal: &Self::Goal) -> bool {
+        goal.contains(&current.pos)
+    }
 
-    /// Convergence occurs adjacent to the goal, not on the goal in this case
-    fn converge(&self, current: &Self::State, goal: &Self::State) -> bool {
-        let (x, y) = (i64::from(current.pos.x), i64::from(current.pos.y));
-        let (gx, gy) = (i64::from(goal.pos.x), i64::from(goal.pos.y));
+    fn validate(&self, start: &Actor, goal: &Target) -> Result<(), PathFindingErr> {
+        if !self.map.is_passable(&start.pos) {
+            return Err(PathFindingErr::StartInvalid(Diagnostics::default()));
+        }
+        goal.validate(&self.map)?;
 
-        (x - gx).abs() <= 1 && (y - gy).abs() <= 1
+        match &self.components {
+            Some(components) => goal.validate_connected(components, &start.pos),
+            None => Ok(()),
+        }
     }
 
     fn integrate(
@@ -400,7 +794,20 @@ impl Model for TurnOptimal {
 
 impl HeuristicModel for TurnOptimal {
     /// Reasonable estimate for the number of turns required to reach the player
-    fn heuristic(&self, current: &Self::State, goal: &Self::State) -> Self::Cost {
-        self.heurisitc.calculate(current.pos.clone().into(), goal.pos.clone().into())
+    fn heuristic(&self, current: &Self::State, goal: &Self::Goal) -> Self::Cost {
+        // The nearest position of a region in each axis may be far from the nearest one
+        // around the walls, so the landmarks bound the whole target instead
+        let (heuristic, landmarks) = match &self.heurisitc {
+            Heuristic::Landmarks(landmarks) => {
+                (&Heuristic::Diagonal, landmarks.estimate(&current.pos, goal))
+            }
+            heuristic => (heuristic, 0),
+        };
+
+        goal.estimate(&current.pos, |pos, goal| {
+            heuristic.calculate(pos.clone().into(), goal.clone().into())
+        })
+        .unwrap_or(0)
+        .max(landmarks)
     }
 }
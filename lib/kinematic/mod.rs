@@ -0,0 +1,371 @@
+//! Planning smooth motions in continuous space
+//!
+//! SBMPO does not need its states to lie on a grid: states are continuous, and only
+//! de-duplicated by the cell of an _implicit grid_ they fall into.  Two states which land in
+//! the same cell are considered the same by the optimizer, so the resolution of the grid
+//! trades the quality of the plan against the number of states explored.
+//!
+//! The [`Kinematic`] model plans for an entity with a continuous [`Pose`] which is moved by
+//! sampled [`Velocity`] controls over a [`Map`] treated as an occupancy field.  The model
+//! holds the implicit grid, and poses are created by the model so that every state of a
+//! search falls into a cell of the same grid.
+//!
+//! ```
+//! # use game_lib::kinematic::*;
+//! # use game_lib::map::{Map, Tile};
+//! use game_lib::path::{astar::AStar, Optimizer, PathResult};
+//!
+//! let mut map = Map::new(10, 10);
+//! for y in 1..9 {
+//!     for x in 1..9 {
+//!         map[(x, y)] = Tile::FLOOR;
+//!     }
+//! }
+//!
+//! let mut model = Kinematic::new(map, ImplicitGrid::new(0.5));
+//! let mut sampler = VelocitySampler::new(vec![1.0], 16);
+//!
+//! let start = model.pose(1.5, 1.5, 0.0);
+//! let goal = Waypoint::new(7.5, 7.5, 0.5);
+//!
+//! let mut planner = AStar::new();
+//! match planner.optimize(&mut model, &start, &goal, &mut sampler) {
+//!     PathResult::Final(trajectory) => {
+//!         let (end, _) = trajectory.trajectory.last().unwrap();
+//!         assert!(goal.contains(end));
+//!     }
+//!     _ => panic!("the goal is reachable"),
+//! }
+//! ```
+//!
+//! [`Kinematic`]: struct.Kinematic.html
+//! [`Pose`]: struct.Pose.html
+//! [`Velocity`]: struct.Velocity.html
+//! [`Map`]: ../map/struct.Map.html
+
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+use crate::map::Map;
+use crate::path::{Diagnostics, HeuristicModel, Model, PathFindingErr, Sampler, State};
+
+/// Number of cost units per tile travelled
+///
+/// Costs must be totally ordered, so distances are measured in thousandths of a tile.
+pub const COST_SCALE: f64 = 1000.0;
+
+/// An implicit grid which de-duplicates continuous states
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImplicitGrid {
+    /// Width and height of a cell, in tiles
+    pub resolution: f64,
+    /// Number of bins headings are divided into; headings are ignored when less than 2
+    pub headings: u32,
+}
+
+impl ImplicitGrid {
+    /// A grid of square cells which are `resolution` tiles wide, ignoring headings
+    pub fn new(resolution: f64) -> Self {
+        ImplicitGrid { resolution, headings: 1 }
+    }
+
+    /// Also distinguish states by their heading, divided into `bins` equal bins
+    pub fn with_headings(mut self, bins: u32) -> Self {
+        self.headings = bins;
+        self
+    }
+
+    /// The cell which contains a continuous position and heading
+    pub fn cell(&self, x: f64, y: f64, heading: f64) -> Cell {
+        let heading = if self.headings < 2 {
+            0
+        } else {
+            let turn = normalize(heading) / (2.0 * PI);
+            (turn * f64::from(self.headings)).floor() as u32 % self.headings
+        };
+
+        Cell {
+            x: (x / self.resolution).floor() as i64,
+            y: (y / self.resolution).floor() as i64,
+            heading,
+        }
+    }
+}
+
+impl Default for ImplicitGrid {
+    fn default() -> Self {
+        ImplicitGrid::new(1.0)
+    }
+}
+
+/// A cell of an implicit grid
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cell {
+    pub x: i64,
+    pub y: i64,
+    /// Index of the heading bin
+    pub heading: u32,
+}
+
+/// The continuous position and heading of an entity
+///
+/// Positions are measured in tiles, so the tile at `(x, y)` covers the area from `(x, y)` up
+/// to `(x + 1, y + 1)`.  Headings are measured in radians counter-clockwise from the x-axis.
+///
+/// Poses are created by a [`Kinematic`] model, which places them in a cell of its implicit
+/// grid once, so the pose cannot be moved afterwards.
+///
+/// [`Kinematic`]: struct.Kinematic.html
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pose {
+    x: f64,
+    y: f64,
+    heading: f64,
+    /// The cell of the model's implicit grid the pose is de-duplicated with
+    cell: Cell,
+}
+
+impl Pose {
+    fn new(x: f64, y: f64, heading: f64, grid: &ImplicitGrid) -> Self {
+        let heading = normalize(heading);
+        Pose { x, y, heading, cell: grid.cell(x, y, heading) }
+    }
+
+    pub fn x(&self) -> f64 {
+        self.x
+    }
+
+    pub fn y(&self) -> f64 {
+        self.y
+    }
+
+    pub fn heading(&self) -> f64 {
+        self.heading
+    }
+
+    /// Euclidean distance between the positions of two poses
+    pub fn distance(&self, other: &Pose) -> f64 {
+        let (dx, dy) = (self.x - other.x, self.y - other.y);
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    /// The tile the pose is inside of
+    pub fn tile(&self) -> Option<crate::Position> {
+        if self.x < 0.0 || self.y < 0.0 {
+            None
+        } else {
+            Some(crate::Position::new(self.x as u32, self.y as u32))
+        }
+    }
+}
+
+impl State for Pose {
+    type Position = Cell;
+
+    fn grid_position(&self) -> Self::Position {
+        self.cell.clone()
+    }
+
+    fn tile(&self) -> Option<crate::Position> {
+        Pose::tile(self)
+    }
+}
+
+/// A point to plan to, reached anywhere within its radius
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waypoint {
+    pub x: f64,
+    pub y: f64,
+    pub radius: f64,
+}
+
+impl Waypoint {
+    pub fn new(x: f64, y: f64, radius: f64) -> Self {
+        Waypoint { x, y, radius }
+    }
+
+    /// Distance from a pose to the center of the waypoint
+    pub fn distance(&self, pose: &Pose) -> f64 {
+        let (dx, dy) = (pose.x - self.x, pose.y - self.y);
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    /// Test if a pose has reached the waypoint, regardless of its heading
+    pub fn contains(&self, pose: &Pose) -> bool {
+        self.distance(pose) <= self.radius
+    }
+}
+
+/// Move at a constant speed along a heading for one time step
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Velocity {
+    /// Tiles travelled per time step
+    pub speed: f64,
+    /// Absolute heading, in radians counter-clockwise from the x-axis
+    pub heading: f64,
+}
+
+impl Velocity {
+    pub fn new(speed: f64, heading: f64) -> Self {
+        Velocity { speed, heading: normalize(heading) }
+    }
+}
+
+/// Plan continuous motions over a map sampled as an occupancy field
+#[derive(Clone, Debug)]
+pub struct Kinematic {
+    map: Arc<Map>,
+    grid: ImplicitGrid,
+}
+
+impl Kinematic {
+    /// Move over a map, which is shared rather than copied when given as an `Arc`
+    pub fn new<T>(map: T, grid: ImplicitGrid) -> Self
+    where
+        T: Into<Arc<Map>>,
+    {
+        Kinematic { map: map.into(), grid }
+    }
+
+    pub fn grid(&self) -> &ImplicitGrid {
+        &self.grid
+    }
+
+    pub fn map(&self) -> &Arc<Map> {
+        &self.map
+    }
+
+    /// A pose in the implicit grid of the model
+    pub fn pose(&self, x: f64, y: f64, heading: f64) -> Pose {
+        Pose::new(x, y, heading, &self.grid)
+    }
+
+    /// A pose at the centre of a tile
+    pub fn pose_at(&self, pos: &crate::Position, heading: f64) -> Pose {
+        self.pose(f64::from(pos.x) + 0.5, f64::from(pos.y) + 0.5, heading)
+    }
+
+    /// Take the map back, copying it only when it is still shared
+    pub fn return_map(self) -> Map {
+        Arc::try_unwrap(self.map).unwrap_or_else(|map| (*map).clone())
+    }
+
+    /// Test that the straight line between two points does not cross an occupied tile
+    ///
+    /// The segment is sampled at intervals of a quarter of a tile.
+    fn is_free(&self, (x0, y0): (f64, f64), (x1, y1): (f64, f64)) -> bool {
+        let (dx, dy) = (x1 - x0, y1 - y0);
+        let steps = ((dx * dx + dy * dy).sqrt() * 4.0).ceil().max(1.0) as u32;
+
+        (0..=steps).all(|i| {
+            let t = f64::from(i) / f64::from(steps);
+            !self.map.is_occupied(x0 + t * dx, y0 + t * dy)
+        })
+    }
+}
+
+impl Model for Kinematic {
+    type State = Pose;
+    type Control = Velocity;
+    type Cost = u64;
+    type Goal = Waypoint;
+
+    /// Distance travelled
+    fn cost(&self, current: &Pose, _: &Velocity, next: &Pose) -> Self::Cost {
+        (current.distance(next) * COST_SCALE).ceil() as u64
+    }
+
+    /// Nothing to do on initialization
+    #[inline(always)]
+    fn init(&mut self, _: &Pose) {}
+
+    fn converge(&self, current: &Pose, goal: &Waypoint) -> bool {
+        goal.contains(current)
+    }
+
+    /// Reject starts inside of an occupied tile, goals are only found by searching
+    fn validate(&self, start: &Pose, _: &Waypoint) -> Result<(), PathFindingErr> {
+        if self.map.is_occupied(start.x, start.y) {
+            Err(PathFindingErr::StartInvalid(Diagnostics::default()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn integrate(&self, previous: &Pose, control: &Velocity) -> Option<Pose> {
+        let x = previous.x + control.speed * control.heading.cos();
+        let y = previous.y + control.speed * control.heading.sin();
+
+        if self.is_free((previous.x, previous.y), (x, y)) {
+            Some(self.pose(x, y, control.heading))
+        } else {
+            None
+        }
+    }
+}
+
+impl HeuristicModel for Kinematic {
+    /// Straight-line distance to the edge of the goal
+    fn heuristic(&self, current: &Pose, goal: &Waypoint) -> Self::Cost {
+        let remaining = (goal.distance(current) - goal.radius).max(0.0);
+        (remaining * COST_SCALE).floor() as u64
+    }
+}
+
+/// Sample velocities in evenly spaced headings around the current heading
+///
+/// Every speed is sampled in each heading.  Limiting the turn rate only samples the headings
+/// within `max_turn` radians of the current heading, which produces smoother motions.
+#[derive(Debug, Clone)]
+pub struct VelocitySampler {
+    speeds: Vec<f64>,
+    headings: u32,
+    max_turn: f64,
+    buffer: Vec<Velocity>,
+}
+
+impl VelocitySampler {
+    /// Sample every speed in `headings` headings evenly spaced around the circle
+    pub fn new(speeds: Vec<f64>, headings: u32) -> Self {
+        VelocitySampler { speeds, headings, max_turn: PI, buffer: Vec::new() }
+    }
+
+    /// Only sample headings within `max_turn` radians of the current heading
+    pub fn with_max_turn(mut self, max_turn: f64) -> Self {
+        self.max_turn = max_turn.abs().min(PI);
+        self
+    }
+}
+
+impl Sampler<Kinematic> for VelocitySampler {
+    fn sample(&mut self, _: &Kinematic, current: &Pose) -> &[Velocity] {
+        self.buffer.clear();
+
+        let step = 2.0 * PI / f64::from(self.headings.max(1));
+        for i in 0..self.headings {
+            // Offsets alternate around the current heading: 0, +step, -step, +2 step, ...
+            let k = f64::from(i.div_ceil(2));
+            let offset = if i % 2 == 0 { -k * step } else { k * step };
+            if offset.abs() > self.max_turn + 1e-9 {
+                continue;
+            }
+
+            for &speed in self.speeds.iter() {
+                self.buffer.push(Velocity::new(speed, current.heading + offset));
+            }
+        }
+
+        &self.buffer
+    }
+}
+
+/// Wrap an angle into the range `[0, 2π)`
+fn normalize(angle: f64) -> f64 {
+    let turn = 2.0 * PI;
+    let angle = angle % turn;
+    if angle < 0.0 {
+        angle + turn
+    } else {
+        angle
+    }
+}
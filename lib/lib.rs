@@ -1,5 +1,7 @@
 pub mod actor;
+pub mod kinematic;
 pub mod map;
+pub mod nav;
 pub mod path;
 
 use std::ops::{Add, Mul, Sub};
@@ -37,6 +39,10 @@ impl path::State for Position {
     fn grid_position(&self) -> Self::Position {
         self.clone()
     }
+
+    fn tile(&self) -> Option<Position> {
+        Some(self.clone())
+    }
 }
 
 impl Add for Position {
@@ -0,0 +1,250 @@
+use super::Map;
+use crate::actor::Direction;
+use crate::Position;
+
+/// Which neighbours of a tile are connected to it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Connectivity {
+    /// Only the tiles sharing an edge
+    Four,
+    /// The tiles sharing an edge or a corner, which is how entities walk on a map
+    Eight,
+}
+
+/// The connected components of the passable tiles of a map
+///
+/// Every passable tile is labeled with the component it belongs to, so testing whether one
+/// tile can be reached from another only compares their labels, instead of searching the
+/// whole area around them.  Labels are reused once their component disappears.
+///
+/// The labels must be updated with [`update`] every time a tile of the map changes.
+///
+/// ```
+/// # use game_lib::map::{Map, Tile};
+/// # use game_lib::Position;
+/// use game_lib::map::{Components, Connectivity};
+///
+/// // Two rooms which only touch at a corner
+/// let mut map = Map::new(7, 7);
+/// for &(x, y) in [(1, 1), (2, 1), (1, 2), (2, 2), (3, 3), (4, 3), (3, 4), (4, 4)].iter() {
+///     map[(x, y)] = Tile::FLOOR;
+/// }
+///
+/// let (a, b) = (Position::new(1, 1), Position::new(4, 4));
+/// let mut four = Components::new(&map, Connectivity::Four);
+/// assert!(!four.connected(&a, &b));
+/// assert!(Components::new(&map, Connectivity::Eight).connected(&a, &b));
+///
+/// map[(3, 2)] = Tile::FLOOR;
+/// four.update(&map, &Position::new(3, 2));
+/// assert!(four.connected(&a, &b));
+/// assert_eq!(four.size(four.label(&a).unwrap()), 9);
+/// ```
+///
+/// [`update`]: #method.update
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Components {
+    connectivity: Connectivity,
+    width: u32,
+    height: u32,
+    labels: Vec<Option<u32>>,
+    sizes: Vec<usize>,
+    free: Vec<u32>,
+}
+
+impl Components {
+    /// Label the components of a map
+    pub fn new(map: &Map, connectivity: Connectivity) -> Self {
+        let (width, height) = map.dimensions();
+        let mut components = Components {
+            connectivity,
+            width,
+            height,
+            labels: vec![None; width as usize * height as usize],
+            sizes: Vec::new(),
+            free: Vec::new(),
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                let pos = Position::new(x, y);
+                if components.labels[components.index(&pos)].is_none() && map.is_passable(&pos)
+                {
+                    let label = components.allocate();
+                    components.sizes[label as usize] =
+                        components.relabel(map, pos, None, Some(label));
+                }
+            }
+        }
+
+        components
+    }
+
+    /// The width and height of the labeled map
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn connectivity(&self) -> Connectivity {
+        self.connectivity
+    }
+
+    /// The label of the component of a tile, if the tile is passable
+    pub fn label(&self, pos: &Position) -> Option<u32> {
+        if self.contains(pos) {
+            self.labels[self.index(pos)]
+        } else {
+            None
+        }
+    }
+
+    /// The number of tiles in a component
+    pub fn size(&self, label: u32) -> usize {
+        self.sizes.get(label as usize).cloned().unwrap_or(0)
+    }
+
+    /// Test if two passable tiles belong to the same component
+    pub fn connected(&self, a: &Position, b: &Position) -> bool {
+        match (self.label(a), self.label(b)) {
+            (Some(a), Some(b)) => a == b,
+            _ => false,
+        }
+    }
+
+    /// Every component with its size
+    pub fn iter(&self) -> impl Iterator<Item = (u32, usize)> + '_ {
+        self.sizes
+            .iter()
+            .enumerate()
+            .filter(|(_, &size)| size > 0)
+            .map(|(label, &size)| (label as u32, size))
+    }
+
+    /// The label of the component with the most tiles
+    pub fn largest(&self) -> Option<u32> {
+        self.iter().max_by_key(|&(_, size)| size).map(|(label, _)| label)
+    }
+
+    /// Update the labels after the tile at `pos` changed
+    ///
+    /// Opening a tile joins the components around it into the largest one, and closing a
+    /// tile relabels its component, which may have been split in pieces.  Both only visit the
+    /// components next to the tile.
+    pub fn update(&mut self, map: &Map, pos: &Position) {
+        if !self.contains(pos) {
+            return;
+        }
+
+        let index = self.index(pos);
+        match (self.labels[index], map.is_passable(pos)) {
+            (None, true) => {
+                let keep = self
+                    .neighbours(pos)
+                    .filter_map(|next| self.labels[self.index(&next)])
+                    .max_by_key(|&label| self.sizes[label as usize])
+                    .unwrap_or_else(|| self.allocate());
+
+                self.labels[index] = Some(keep);
+                self.sizes[keep as usize] += 1;
+
+                for next in self.neighbours(pos) {
+                    if let Some(other) = self.labels[self.index(&next)].filter(|&l| l != keep)
+                    {
+                        let moved = self.relabel(map, next, Some(other), Some(keep));
+                        self.sizes[keep as usize] += moved;
+                        self.release(other);
+                    }
+                }
+            }
+            (Some(old), false) => {
+                self.labels[index] = None;
+                let neighbours: Vec<Position> = self.neighbours(pos).collect();
+
+                for next in &neighbours {
+                    if self.labels[self.index(next)] == Some(old) {
+                        self.relabel(map, next.clone(), Some(old), None);
+                    }
+                }
+                self.release(old);
+
+                for next in neighbours {
+                    if self.labels[self.index(&next)].is_none() && map.is_passable(&next) {
+                        let label = self.allocate();
+                        self.sizes[label as usize] =
+                            self.relabel(map, next, None, Some(label));
+                    }
+                }
+            }
+            // The tile changed without opening or closing
+            _ => {}
+        }
+    }
+
+    /// Flood the passable tiles labeled `from` around `start` with the label `to`
+    ///
+    /// Returns the number of tiles relabeled.
+    fn relabel(
+        &mut self,
+        map: &Map,
+        start: Position,
+        from: Option<u32>,
+        to: Option<u32>,
+    ) -> usize {
+        let start_index = self.index(&start);
+        self.labels[start_index] = to;
+
+        let mut count = 1;
+        let mut stack = vec![start];
+        while let Some(pos) = stack.pop() {
+            for next in self.neighbours(&pos) {
+                let index = self.index(&next);
+                if self.labels[index] == from && map.is_passable(&next) {
+                    self.labels[index] = to;
+                    count += 1;
+                    stack.push(next);
+                }
+            }
+        }
+
+        count
+    }
+
+    fn allocate(&mut self) -> u32 {
+        match self.free.pop() {
+            Some(label) => label,
+            None => {
+                self.sizes.push(0);
+                (self.sizes.len() - 1) as u32
+            }
+        }
+    }
+
+    fn release(&mut self, label: u32) {
+        self.sizes[label as usize] = 0;
+        self.free.push(label);
+    }
+
+    /// The connected neighbours of a tile which lie on the map
+    fn neighbours(&self, pos: &Position) -> impl Iterator<Item = Position> {
+        let (x, y) = (pos.x, pos.y);
+        let (width, height, connectivity) = (self.width, self.height, self.connectivity);
+
+        Direction::ALL
+            .iter()
+            .filter(move |direction| {
+                connectivity == Connectivity::Eight || !direction.is_diagonal()
+            })
+            .filter_map(move |direction| direction.checked_step(x, y))
+            .filter(move |&(x, y)| x < width && y < height)
+            .map(Position::from)
+    }
+
+    fn contains(&self, pos: &Position) -> bool {
+        pos.x < self.width && pos.y < self.height
+    }
+
+    #[inline(always)]
+    fn index(&self, pos: &Position) -> usize {
+        pos.x as usize + pos.y as usize * self.width as usize
+    }
+}
@@ -0,0 +1,84 @@
+use crate::Position;
+
+/// The tiles on the straight line between two tiles, following Bresenham's algorithm
+///
+/// Consecutive tiles of the line are neighbours, so the line can be walked one step at a
+/// time.  Both ends are part of the line, which starts at `from` and ends at `to`.  The line
+/// from `a` to `b` is not always the line from `b` to `a` in reverse.
+///
+/// ```
+/// # use game_lib::Position;
+/// use game_lib::map::Line;
+///
+/// let line: Vec<Position> = Line::new(&Position::new(0, 0), &Position::new(4, 2)).collect();
+/// assert_eq!(
+///     line,
+///     vec![
+///         Position::new(0, 0),
+///         Position::new(1, 1),
+///         Position::new(2, 1),
+///         Position::new(3, 2),
+///         Position::new(4, 2),
+///     ]
+/// );
+/// ```
+#[derive(Debug, Clone)]
+pub struct Line {
+    x: i64,
+    y: i64,
+    dx: i64,
+    dy: i64,
+    sx: i64,
+    sy: i64,
+    error: i64,
+    remaining: u32,
+}
+
+impl Line {
+    pub fn new(from: &Position, to: &Position) -> Self {
+        let dx = (i64::from(to.x) - i64::from(from.x)).abs();
+        let dy = -(i64::from(to.y) - i64::from(from.y)).abs();
+
+        Line {
+            x: i64::from(from.x),
+            y: i64::from(from.y),
+            dx,
+            dy,
+            sx: if from.x < to.x { 1 } else { -1 },
+            sy: if from.y < to.y { 1 } else { -1 },
+            error: dx + dy,
+            remaining: dx.max(-dy) as u32 + 1,
+        }
+    }
+}
+
+impl Iterator for Line {
+    type Item = Position;
+
+    fn next(&mut self) -> Option<Position> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let current = Position::new(self.x as u32, self.y as u32);
+
+        let double = 2 * self.error;
+        if double >= self.dy {
+            self.error += self.dy;
+            self.x += self.sx;
+        }
+        if double <= self.dx {
+            self.error += self.dx;
+            self.y += self.sy;
+        }
+
+        Some(current)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
+
+impl ExactSizeIterator for Line {}
@@ -1,8 +1,18 @@
 use std::collections::HashSet;
+use std::hash::Hasher;
 use std::ops::{Index, IndexMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use fnv::FnvHasher;
 
 use super::{Position, Rect};
 
+mod components;
+mod line;
+
+pub use self::components::{Components, Connectivity};
+pub use self::line::Line;
+
 /// A Tile on the map
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Tile {
@@ -39,11 +49,15 @@ pub enum MapError {
     InfiniteLoop,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// The last revision given to a map, shared by every map so that no two maps share one
+static REVISION: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Eq)]
 pub struct Map {
     tiles: Vec<Tile>,
     width: u32,
     height: u32,
+    revision: u64,
 }
 
 impl Map {
@@ -53,7 +67,28 @@ impl Map {
     /// carved out of the map.
     pub fn new(width: u32, height: u32) -> Self {
         let tiles = vec![Tile::WALL; (width * height) as usize];
-        Map { tiles, width, height }
+        Map { tiles, width, height, revision: next_revision() }
+    }
+
+    /// Identify the content of the map, which changes whenever a tile may have changed
+    ///
+    /// Every new map gets a revision of its own, and so does every mutable borrow of a tile,
+    /// while clones keep the revision of their original.  Data built from a map, such as a
+    /// model planning on it, can compare revisions to tell whether it is out of date without
+    /// looking at the tiles.
+    ///
+    /// ```
+    /// # use game_lib::map::{Map, Tile};
+    /// let mut map = Map::new(4, 4);
+    /// let copy = map.clone();
+    /// assert_eq!(map.revision(), copy.revision());
+    ///
+    /// map[(1, 1)] = Tile::FLOOR;
+    /// assert_ne!(map.revision(), copy.revision());
+    /// assert_ne!(Map::new(4, 4).revision(), copy.revision());
+    /// ```
+    pub fn revision(&self) -> u64 {
+        self.revision
     }
 
     /// The width and height of the map
@@ -69,6 +104,36 @@ impl Map {
         (self.width, self.height)
     }
 
+    /// Test if a position lies on the map
+    pub fn contains(&self, pos: &Position) -> bool {
+        pos.x < self.width && pos.y < self.height
+    }
+
+    /// Test if a position lies on the map on a tile which does not block movement
+    pub fn is_passable(&self, pos: &Position) -> bool {
+        self.contains(pos) && !self[pos.clone()].is_blocking()
+    }
+
+    /// Test if every tile on the [`Line`] from one position to another is passable
+    ///
+    /// ```
+    /// # use game_lib::map::{Map, Tile};
+    /// # use game_lib::Position;
+    /// let mut map = Map::new(5, 3);
+    /// for x in 0..5 {
+    ///     map[(x, 1)] = Tile::FLOOR;
+    /// }
+    /// assert!(map.line_of_sight(&Position::new(0, 1), &Position::new(4, 1)));
+    ///
+    /// map[(2, 1)] = Tile::BLOCK;
+    /// assert!(!map.line_of_sight(&Position::new(0, 1), &Position::new(4, 1)));
+    /// ```
+    ///
+    /// [`Line`]: struct.Line.html
+    pub fn line_of_sight(&self, from: &Position, to: &Position) -> bool {
+        Line::new(from, to).all(|pos| self.is_passable(&pos))
+    }
+
     /// Convert two values from a subscript into an index to the tile vector
     #[inline(always)]
     fn sub2ind(&self, x: u32, y: u32) -> usize {
@@ -81,6 +146,7 @@ impl Map {
     }
 
     pub fn pos_mut(&mut self, pos: &Position) -> Option<&mut Tile> {
+        self.revision = next_revision();
         let index = self.sub2ind(pos.x, pos.y);
         self.tiles.get_mut(index)
     }
@@ -93,10 +159,32 @@ impl Map {
 
     /// Get a mutable reference to a tile, if it exists in the Map
     pub fn get_mut(&mut self, x: u32, y: u32) -> Option<&mut Tile> {
+        self.revision = next_revision();
         let index = self.sub2ind(x, y);
         self.tiles.get_mut(index)
     }
 
+    /// Sample the map as a continuous occupancy field
+    ///
+    /// The tile at `(x, y)` covers the area from `(x, y)` up to `(x + 1, y + 1)`.  Points
+    /// outside of the map are occupied.
+    ///
+    /// ```
+    /// # use game_lib::map::{Map, Tile};
+    /// let mut map = Map::new(3, 3);
+    /// map[(1, 1)] = Tile::FLOOR;
+    /// assert!(!map.is_occupied(1.5, 1.9));
+    /// assert!(map.is_occupied(2.1, 1.5));
+    /// assert!(map.is_occupied(-0.5, 1.5));
+    /// ```
+    pub fn is_occupied(&self, x: f64, y: f64) -> bool {
+        if x < 0.0 || y < 0.0 || x >= f64::from(self.width) || y >= f64::from(self.height) {
+            return true;
+        }
+
+        self.get(x as u32, y as u32).map(Tile::is_blocking).unwrap_or(true)
+    }
+
     /// Run some operation on tiles adjacent to `(x, y)`
     ///
     /// Iterates over the tiles in the order they are laid out in memory.
@@ -164,10 +252,7 @@ impl Map {
         }
 
         let mut queue = vec![(x, y)];
-        while !queue.is_empty() {
-            // Queue should never be empty, so pop the last element without checking
-            let (x, y) = queue.pop().unwrap();
-
+        while let Some((x, y)) = queue.pop() {
             // Clamp lower bounds to zero to prevent underflow
             for y in y.max(1) - 1..y + 2 {
                 for x in x.max(1) - 1..x + 2 {
@@ -221,8 +306,7 @@ impl Map {
 
         let mut size = 0;
         let mut queue = vec![(x, y)];
-        while queue.is_empty() {
-            let (x, y) = queue.pop().unwrap();
+        while let Some((x, y)) = queue.pop() {
             // prevent underflow
             for y in y.max(1) - 1..y + 2 {
                 for x in x.max(1) - 1..x + 2 {
@@ -242,12 +326,44 @@ impl Map {
         Ok(size)
     }
 
+    /// Hash the parts of the map which movement depends on
+    ///
+    /// The hash covers the dimensions and which tiles block movement, but not whether tiles
+    /// were explored, so data precomputed for a map stays valid while it is explored.  The
+    /// FNV hash is stable across runs and platforms, so it can be stored along with the data.
+    ///
+    /// ```
+    /// # use game_lib::map::{Map, Tile};
+    /// let mut map = Map::new(4, 4);
+    /// let walls = map.content_hash();
+    ///
+    /// map[(1, 1)] = Tile::FLOOR;
+    /// assert_ne!(map.content_hash(), walls);
+    /// assert_ne!(Map::new(2, 8).content_hash(), walls);
+    /// ```
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = FnvHasher::default();
+        hasher.write_u32(self.width);
+        hasher.write_u32(self.height);
+        for tile in &self.tiles {
+            hasher.write_u8(tile.is_blocking() as u8);
+        }
+        hasher.finish()
+    }
+
     /// Iterate over the tiles inside a rectangular area contained in the map
     pub fn iter_rect(&self, area: Rect) -> MapArea<'_> {
         MapArea { x: 0, y: 0, area, map: self }
     }
 }
 
+/// Maps are equal when their tiles are, whatever their revisions
+impl PartialEq for Map {
+    fn eq(&self, other: &Self) -> bool {
+        (self.width, self.height) == (other.width, other.height) && self.tiles == other.tiles
+    }
+}
+
 impl Index<(u32, u32)> for Map {
     type Output = Tile;
 
@@ -267,6 +383,7 @@ impl IndexMut<(u32, u32)> for Map {
             panic!("Index ({}, {}) out of bounds ({}, {})", x, y, self.width, self.height);
         }
 
+        self.revision = next_revision();
         let index = self.sub2ind(x, y);
         &mut self.tiles[index]
     }
@@ -291,11 +408,16 @@ impl IndexMut<Position> for Map {
             panic!("Index ({}, {}) out of bounds ({}, {})", x, y, self.width, self.height);
         }
 
+        self.revision = next_revision();
         let index = self.sub2ind(x, y);
         &mut self.tiles[index]
     }
 }
 
+fn next_revision() -> u64 {
+    REVISION.fetch_add(1, Ordering::Relaxed) + 1
+}
+
 /// Iterate over the tiles inside a rectangular area contained in the map
 ///
 /// See `Map::iter_rect`
@@ -375,32 +497,19 @@ where
             map = next.clone();
         }
 
-        let mut clusters: Vec<(u32, u32, usize)> = Vec::new();
-        let mut cluster_map = map.clone();
-        for y in 1..height - 1 {
-            for x in 1..width - 1 {
-                if cluster_map[(x, y)].is_wall() {
-                    continue;
-                }
-
-                if let Ok(size) =
-                    cluster_map.flood_replace(x, y, |tile| !tile.is_wall(), Tile::WALL)
-                {
-                    clusters.push((x, y, size));
+        // Only keep the largest cave, so that every floor can be reached from any other
+        let components = Components::new(&map, Connectivity::Eight);
+        if let Some(largest) = components.largest() {
+            for y in 1..height - 1 {
+                for x in 1..width - 1 {
+                    let label = components.label(&Position::new(x, y));
+                    if label.is_some() && label != Some(largest) {
+                        map[(x, y)] = Tile::WALL;
+                    }
                 }
             }
         }
 
-        clusters.sort_by(|c1, c2| c1.2.cmp(&c2.2));
-        clusters.pop();
-
-        for (x, y, _) in clusters {
-            match map.flood_replace(x, y, |tile| !tile.is_wall(), Tile::WALL) {
-                Ok(_) => continue,
-                Err(MapError::InfiniteLoop) => continue,
-            }
-        }
-
         let mut count = 0.0;
         for tile in map.tiles.iter() {
             if !tile.is_wall() {
@@ -0,0 +1,451 @@
+//! Contraction hierarchies, which answer shortest-path queries on maps which never change
+//!
+//! Preprocessing ranks the passable tiles of a map by importance and _contracts_ them one by
+//! one, from the least to the most important: a tile is removed from the graph, and
+//! _shortcuts_ replace the cheapest paths which went through it.  Every tile keeps the edges
+//! to the tiles which outrank it.
+//!
+//! A query then searches upward from both the start and the goal at once, only ever following
+//! edges to more important tiles, and meets in the middle.  Both searches settle a tiny part
+//! of the map, so long queries are orders of magnitude faster than with A*, and the
+//! shortcuts are unpacked back into the steps they stand for.
+//!
+//! ```
+//! # use game_lib::map::{Map, Tile};
+//! # use game_lib::Position;
+//! use game_lib::actor::{Actor, Movement, TurnOptimal};
+//! use game_lib::nav::ContractionHierarchy;
+//!
+//! let mut map = Map::new(20, 10);
+//! for y in 1..9 {
+//!     for x in 1..19 {
+//!         map[(x, y)] = Tile::FLOOR;
+//!     }
+//! }
+//! for y in 1..8 {
+//!     map[(10, y)] = Tile::WALL;
+//! }
+//!
+//! let hierarchy = ContractionHierarchy::new(&map);
+//! let model = TurnOptimal::new(map);
+//! let start = Actor::new(2, 2, 0, 0);
+//!
+//! let trajectory = hierarchy.trajectory(&model, &start, &Position::new(17, 2)).unwrap();
+//! assert_eq!(trajectory.trajectory.last().unwrap().0.pos, Position::new(17, 2));
+//! assert!(trajectory.trajectory[1..].iter().all(|(_, m)| match m {
+//!     Movement::Walk(_) => true,
+//!     _ => false,
+//! }));
+//! // Down to the gap under the wall and back up
+//! assert_eq!(trajectory.cost, 12 * 3 + 3 * 2);
+//! ```
+
+use crate::actor::{Actor, Direction, Movement, TurnOptimal};
+use crate::map::Map;
+use crate::path::{Diagnostics, Model, PathFindingErr, Trajectory};
+use crate::Position;
+
+use super::step_cost;
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// Most nodes a witness search settles before assuming there is no witness
+const WITNESS_LIMIT: usize = 64;
+
+/// Marks an edge which is not a shortcut
+const NO_MIDDLE: u32 = u32::MAX;
+
+/// An edge toward a tile which was contracted later
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Edge {
+    to: u32,
+    cost: u32,
+    /// The tile the shortcut bypasses, or `NO_MIDDLE` for a step between neighbours
+    middle: u32,
+}
+
+/// The passable tiles of a map, contracted in order of importance
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContractionHierarchy {
+    width: u32,
+    height: u32,
+    nodes: Vec<Option<u32>>,
+    positions: Vec<Position>,
+    up: Vec<Vec<Edge>>,
+    shortcuts: usize,
+}
+
+impl ContractionHierarchy {
+    /// Contract the graph of the passable tiles of a map, walking in eight directions
+    pub fn new(map: &Map) -> Self {
+        let (width, height) = map.dimensions();
+        let mut nodes = vec![None; width as usize * height as usize];
+        let mut positions = Vec::new();
+
+        for y in 0..height {
+            for x in 0..width {
+                let pos = Position::new(x, y);
+                if map.is_passable(&pos) {
+                    nodes[x as usize + y as usize * width as usize] =
+                        Some(positions.len() as u32);
+                    positions.push(pos);
+                }
+            }
+        }
+
+        let mut graph: Vec<Vec<Edge>> = positions
+            .iter()
+            .map(|pos| {
+                Direction::ALL
+                    .iter()
+                    .filter_map(|&direction| {
+                        let (x, y) = direction.checked_step(pos.x, pos.y)?;
+                        let to = nodes.get(x as usize + y as usize * width as usize)?;
+                        to.filter(|_| x < width && y < height).map(|to| Edge {
+                            to,
+                            cost: step_cost(direction) as u32,
+                            middle: NO_MIDDLE,
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let (up, shortcuts) = Contraction::new(positions.len()).run(&mut graph);
+        ContractionHierarchy { width, height, nodes, positions, up, shortcuts }
+    }
+
+    /// The number of shortcuts added by the contraction
+    pub fn shortcuts(&self) -> usize {
+        self.shortcuts
+    }
+
+    /// The cost and every position of the cheapest path between two positions
+    pub fn shortest_path(
+        &self,
+        start: &Position,
+        goal: &Position,
+    ) -> Result<(usize, Vec<Position>), PathFindingErr> {
+        let start =
+            self.node(start).ok_or(PathFindingErr::StartInvalid(Diagnostics::default()))?;
+        let goal = match self.node(goal) {
+            Some(goal) => goal,
+            None if goal.x < self.width && goal.y < self.height => {
+                return Err(PathFindingErr::GoalBlocked(Diagnostics::default()))
+            }
+            None => return Err(PathFindingErr::GoalOutOfBounds(Diagnostics::default())),
+        };
+
+        let mut forward = Search::new(start);
+        let mut backward = Search::new(goal);
+        let mut best: Option<(u32, u32)> = None;
+
+        // Alternate between both directions until neither can improve on the best meeting
+        loop {
+            let bound = best.map(|(cost, _)| cost).unwrap_or(u32::MAX);
+            let (search, other) = match (forward.peek(), backward.peek()) {
+                (Some(f), Some(b)) if f.min(b) < bound => {
+                    if f <= b {
+                        (&mut forward, &backward)
+                    } else {
+                        (&mut backward, &forward)
+                    }
+                }
+                (Some(f), None) if f < bound => (&mut forward, &backward),
+                (None, Some(b)) if b < bound => (&mut backward, &forward),
+                _ => break,
+            };
+
+            if let Some((node, cost)) = search.settle(&self.up) {
+                if let Some(meeting) = other.cost(node).map(|other| other + cost) {
+                    if best.map(|(best, _)| meeting < best).unwrap_or(true) {
+                        best = Some((meeting, node));
+                    }
+                }
+            }
+        }
+
+        let (cost, meeting) = match best {
+            Some(best) => best,
+            None => {
+                let closed = forward.settled + backward.settled;
+                return Err(PathFindingErr::Unreachable(Diagnostics {
+                    expansions: closed,
+                    closed,
+                    region: None,
+                }));
+            }
+        };
+
+        // Nodes from the start up to the meeting, and back down to the goal
+        let mut upward = forward.path(meeting);
+        upward.reverse();
+        let downward = backward.path(meeting);
+
+        let mut path = vec![start];
+        for pair in upward.windows(2).chain(downward.windows(2)) {
+            self.unpack(pair[0], pair[1], &mut path);
+        }
+
+        Ok((
+            cost as usize,
+            path.into_iter().map(|node| self.positions[node as usize].clone()).collect(),
+        ))
+    }
+
+    /// The cheapest trajectory for an actor to walk to a goal
+    ///
+    /// The states are integrated with the model, whose map must be the one the hierarchy was
+    /// built from.
+    pub fn trajectory(
+        &self,
+        model: &TurnOptimal,
+        start: &Actor,
+        goal: &Position,
+    ) -> Result<Trajectory<TurnOptimal>, PathFindingErr> {
+        let (_, path) = self.shortest_path(&start.pos, goal)?;
+
+        let mut trajectory = vec![(start.clone(), Movement::default())];
+        let mut cost = 0;
+        for next in path.iter().skip(1) {
+            let current = &trajectory[trajectory.len() - 1].0;
+            let direction = Direction::ALL
+                .iter()
+                .cloned()
+                .find(|direction| {
+                    direction.checked_step(current.pos.x, current.pos.y)
+                        == Some((next.x, next.y))
+                })
+                .ok_or(PathFindingErr::Unreachable(Diagnostics::default()))?;
+
+            let control = Movement::Walk(direction);
+            let state = model
+                .integrate(current, &control)
+                .ok_or(PathFindingErr::Unreachable(Diagnostics::default()))?;
+            cost += model.cost(current, &control, &state);
+            trajectory.push((state, control));
+        }
+
+        Ok(Trajectory { cost, trajectory, goal: 0 })
+    }
+
+    fn node(&self, pos: &Position) -> Option<u32> {
+        if pos.x < self.width && pos.y < self.height {
+            self.nodes[pos.x as usize + pos.y as usize * self.width as usize]
+        } else {
+            None
+        }
+    }
+
+    /// Append the tiles after `from` of the edge from `from` to `to`, bypassing shortcuts
+    fn unpack(&self, from: u32, to: u32, path: &mut Vec<u32>) {
+        let edge = self.edge(from, to);
+        if edge.middle == NO_MIDDLE {
+            path.push(to);
+        } else {
+            self.unpack(from, edge.middle, path);
+            self.unpack(edge.middle, to, path);
+        }
+    }
+
+    /// The cheapest edge between two nodes, which is kept by the one contracted first
+    fn edge(&self, a: u32, b: u32) -> Edge {
+        let find = |from: u32, to: u32| {
+            self.up[from as usize]
+                .iter()
+                .filter(|edge| edge.to == to)
+                .min_by_key(|edge| edge.cost)
+        };
+        *find(a, b).or_else(|| find(b, a)).expect("edges of a path are in the hierarchy")
+    }
+}
+
+/// One direction of a query, which only follows edges upward
+struct Search {
+    queue: BinaryHeap<Reverse<(u32, u32)>>,
+    costs: fnv::FnvHashMap<u32, (u32, u32)>,
+    settled: usize,
+}
+
+impl Search {
+    fn new(source: u32) -> Self {
+        let mut costs = fnv::FnvHashMap::default();
+        costs.insert(source, (0, source));
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse((0, source)));
+        Search { queue, costs, settled: 0 }
+    }
+
+    /// The lowest cost in the queue
+    fn peek(&self) -> Option<u32> {
+        self.queue.peek().map(|Reverse((cost, _))| *cost)
+    }
+
+    fn cost(&self, node: u32) -> Option<u32> {
+        self.costs.get(&node).map(|&(cost, _)| cost)
+    }
+
+    /// Settle the next node, unless it was already settled more cheaply
+    fn settle(&mut self, up: &[Vec<Edge>]) -> Option<(u32, u32)> {
+        let Reverse((cost, node)) = self.queue.pop()?;
+        if self.cost(node) != Some(cost) {
+            return None;
+        }
+        self.settled += 1;
+
+        for edge in &up[node as usize] {
+            let next = cost + edge.cost;
+            if self.cost(edge.to).map(|old| next < old).unwrap_or(true) {
+                self.costs.insert(edge.to, (next, node));
+                self.queue.push(Reverse((next, edge.to)));
+            }
+        }
+
+        Some((node, cost))
+    }
+
+    /// The nodes from `node` back to the source
+    fn path(&self, mut node: u32) -> Vec<u32> {
+        let mut path = vec![node];
+        while let Some(&(_, parent)) = self.costs.get(&node) {
+            if parent == node {
+                break;
+            }
+            node = parent;
+            path.push(node);
+        }
+        path
+    }
+}
+
+/// The state of the graph while it is being contracted
+struct Contraction {
+    contracted: Vec<bool>,
+    deleted_neighbours: Vec<i64>,
+    // Scratch space for witness searches, reset after every search
+    distance: Vec<u32>,
+    touched: Vec<u32>,
+}
+
+impl Contraction {
+    fn new(nodes: usize) -> Self {
+        Contraction {
+            contracted: vec![false; nodes],
+            deleted_neighbours: vec![0; nodes],
+            distance: vec![u32::MAX; nodes],
+            touched: Vec::new(),
+        }
+    }
+
+    /// Contract every node, returning the upward edges of each node and the shortcut count
+    fn run(mut self, graph: &mut [Vec<Edge>]) -> (Vec<Vec<Edge>>, usize) {
+        let nodes = graph.len();
+        let mut queue: BinaryHeap<Reverse<(i64, u32)>> = (0..nodes as u32)
+            .map(|node| Reverse((self.priority(graph, node), node)))
+            .collect();
+
+        let mut up = vec![Vec::new(); nodes];
+        let mut shortcuts = 0;
+
+        while let Some(Reverse((_, node))) = queue.pop() {
+            // Priorities go stale as the graph changes, so check again before contracting
+            let priority = self.priority(graph, node);
+            if let Some(Reverse((next, _))) = queue.peek() {
+                if priority > *next {
+                    queue.push(Reverse((priority, node)));
+                    continue;
+                }
+            }
+
+            for (from, to, cost) in self.shortcuts(graph, node) {
+                shortcuts += 1;
+                for &(a, b) in [(from, to), (to, from)].iter() {
+                    let edges = &mut graph[a as usize];
+                    match edges.iter_mut().find(|edge| edge.to == b) {
+                        Some(edge) if edge.cost <= cost => {}
+                        Some(edge) => *edge = Edge { to: b, cost, middle: node },
+                        None => edges.push(Edge { to: b, cost, middle: node }),
+                    }
+                }
+            }
+
+            self.contracted[node as usize] = true;
+            let edges = std::mem::take(&mut graph[node as usize]);
+            for edge in &edges {
+                graph[edge.to as usize].retain(|back| back.to != node);
+                self.deleted_neighbours[edge.to as usize] += 1;
+            }
+            up[node as usize] = edges;
+        }
+
+        (up, shortcuts)
+    }
+
+    /// Edge difference, plus the neighbours already contracted to spread contraction evenly
+    fn priority(&mut self, graph: &[Vec<Edge>], node: u32) -> i64 {
+        let shortcuts = self.shortcuts(graph, node).len() as i64;
+        shortcuts - graph[node as usize].len() as i64 + self.deleted_neighbours[node as usize]
+    }
+
+    /// The shortcuts needed to contract a node, for paths with no witness around it
+    fn shortcuts(&mut self, graph: &[Vec<Edge>], node: u32) -> Vec<(u32, u32, u32)> {
+        let edges = &graph[node as usize];
+        let mut shortcuts = Vec::new();
+
+        for (i, first) in edges.iter().enumerate() {
+            let limit = edges.iter().skip(i + 1).map(|edge| first.cost + edge.cost).max();
+            let limit = match limit {
+                Some(limit) => limit,
+                None => continue,
+            };
+
+            self.witness(graph, first.to, node, limit);
+            for second in edges.iter().skip(i + 1) {
+                let through = first.cost + second.cost;
+                if self.distance[second.to as usize] > through {
+                    shortcuts.push((first.to, second.to, through));
+                }
+            }
+            self.reset();
+        }
+
+        shortcuts
+    }
+
+    /// A local Dijkstra search from `source` which avoids `avoid`, up to a cost `limit`
+    fn witness(&mut self, graph: &[Vec<Edge>], source: u32, avoid: u32, limit: u32) {
+        let mut queue = BinaryHeap::new();
+        self.distance[source as usize] = 0;
+        self.touched.push(source);
+        queue.push(Reverse((0, source)));
+
+        let mut settled = 0;
+        while let Some(Reverse((cost, node))) = queue.pop() {
+            if cost > self.distance[node as usize] {
+                continue;
+            }
+            settled += 1;
+            if cost > limit || settled > WITNESS_LIMIT {
+                break;
+            }
+
+            for edge in &graph[node as usize] {
+                let next = cost + edge.cost;
+                if edge.to != avoid && next < self.distance[edge.to as usize] {
+                    if self.distance[edge.to as usize] == u32::MAX {
+                        self.touched.push(edge.to);
+                    }
+                    self.distance[edge.to as usize] = next;
+                    queue.push(Reverse((next, edge.to)));
+                }
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        for node in self.touched.drain(..) {
+            self.distance[node as usize] = u32::MAX;
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! Dense distance fields, also known as "Dijkstra maps"
+//!
+//! A distance field stores the cost of the cheapest path from a set of sources to every tile
+//! of a map.  Any number of entities can then move toward the nearest source by stepping
+//! downhill, without planning for themselves.
+//!
+//! ```
+//! # use game_lib::map::{Map, Tile};
+//! # use game_lib::Position;
+//! use game_lib::actor::Direction;
+//! use game_lib::nav::DistanceField;
+//!
+//! let mut map = Map::new(10, 3);
+//! for x in 1..9 {
+//!     map[(x, 1)] = Tile::FLOOR;
+//! }
+//!
+//! let player = Position::new(1, 1);
+//! let field = DistanceField::new(&map, &[(player, 0)]);
+//!
+//! assert_eq!(field.get(&Position::new(4, 1)), Some(6));
+//! assert_eq!(field.get(&Position::new(0, 0)), None);
+//! assert_eq!(field.downhill(&Position::new(4, 1)), Some(Direction::West));
+//!
+//! // Fleeing monsters head away from the player instead
+//! let flee = field.flee(&map, 1.2);
+//! assert_eq!(flee.downhill(&Position::new(4, 1)), Some(Direction::East));
+//! ```
+
+use crate::actor::Direction;
+use crate::map::Map;
+use crate::path::dijkstra::Dijkstra;
+use crate::Position;
+
+use super::{step_cost, Grid, Octile};
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+/// The cost of the cheapest path from a set of weighted sources to every tile of a map
+#[derive(Clone, Debug, Eq)]
+pub struct DistanceField {
+    width: u32,
+    height: u32,
+    /// The costs less the offset, wrapping around below zero
+    costs: Vec<Option<usize>>,
+    /// Added to every stored cost, so that moving the source shifts every cost at once
+    offset: usize,
+}
+
+impl DistanceField {
+    /// Flood the map from a set of sources, each with its own starting cost
+    ///
+    /// Sources with a higher starting cost are less attractive, so a monster will prefer a
+    /// source slightly farther away if it starts cheaper.  Sources on blocked tiles are
+    /// ignored.
+    pub fn new(map: &Map, sources: &[(Position, usize)]) -> Self {
+        let grid = Grid::new(map);
+        let sources = sources.iter().filter(|(pos, _)| grid.is_passable(pos)).cloned();
+
+        let mut dijkstra = Dijkstra::default();
+        dijkstra.flood(&mut Grid::new(map), sources, &mut Octile);
+
+        let (width, height) = map.dimensions();
+        let mut costs = vec![None; width as usize * height as usize];
+        for (pos, cost) in dijkstra.costs() {
+            costs[pos.x as usize + pos.y as usize * width as usize] = Some(cost);
+        }
+
+        DistanceField { width, height, costs, offset: 0 }
+    }
+
+    /// The width and height of the field, which match the map it was created from
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// The cost from the nearest source, or `None` if no source can reach the position
+    pub fn get(&self, pos: &Position) -> Option<usize> {
+        if pos.x < self.width && pos.y < self.height {
+            self.cost(self.index(pos))
+        } else {
+            None
+        }
+    }
+
+    /// The largest cost of any reachable position
+    pub fn max(&self) -> Option<usize> {
+        (0..self.costs.len()).filter_map(|i| self.cost(i)).max()
+    }
+
+    /// Iterate over every reachable position and its cost
+    pub fn iter(&self) -> impl Iterator<Item = (Position, usize)> + '_ {
+        let width = self.width as usize;
+        (0..self.costs.len()).filter_map(move |i| {
+            self.cost(i)
+                .map(|cost| (Position::new((i % width) as u32, (i / width) as u32), cost))
+        })
+    }
+
+    /// The direction of the neighbour with the lowest cost, if it is lower than `pos`
+    ///
+    /// Following the field downhill leads to the nearest source; at a source there is
+    /// nowhere lower to go.
+    pub fn downhill(&self, pos: &Position) -> Option<Direction> {
+        let mut lowest = self.get(pos)?;
+        let mut best = None;
+
+        for &direction in Direction::ALL.iter() {
+            let neighbour = direction.checked_step(pos.x, pos.y).map(Position::from);
+            if let Some(cost) = neighbour.and_then(|n| self.get(&n)) {
+                if cost < lowest {
+                    lowest = cost;
+                    best = Some(direction);
+                }
+            }
+        }
+
+        best
+    }
+
+    /// Move the only source of the field from `from` to `to`, without flooding it again
+    ///
+    /// The field must have been created from the single source `from` with a starting
+    /// cost of `0`.  Walking from `to` back to `from` bounds every new cost by the old one
+    /// plus the cost of `from` in the field, so only the tiles which become cheaper than
+    /// that bound are searched again.  When the goal only moves a few tiles, that is a
+    /// small region around it.  The bound itself is an offset shared by every tile, so the
+    /// rest of the field is left as it is.
+    ///
+    /// Returns the positions whose cost decreased below the bound, or `None` when `to`
+    /// is not reachable from `from`, in which case the field is left untouched.
+    ///
+    /// ```
+    /// # use game_lib::map::{Map, Tile};
+    /// # use game_lib::Position;
+    /// use game_lib::nav::DistanceField;
+    ///
+    /// let mut map = Map::new(20, 12);
+    /// for y in 1..11 {
+    ///     for x in 1..19 {
+    ///         map[(x, y)] = Tile::FLOOR;
+    ///     }
+    /// }
+    /// for y in 3..9 {
+    ///     map[(9, y)] = Tile::WALL;
+    /// }
+    ///
+    /// let mut field = DistanceField::new(&map, &[(Position::new(4, 5), 0)]);
+    /// let path = [Position::new(4, 5), Position::new(5, 5), Position::new(6, 6)];
+    /// for step in path.windows(2) {
+    ///     let changed = field.move_source(&map, &step[0], &step[1]).unwrap();
+    ///     assert!(changed.len() < field.iter().count());
+    /// }
+    ///
+    /// // The same costs as flooding from the new source
+    /// assert_eq!(field, DistanceField::new(&map, &[(Position::new(6, 6), 0)]));
+    /// assert_eq!(field.get(&Position::new(6, 6)), Some(0));
+    /// ```
+    pub fn move_source(
+        &mut self,
+        map: &Map,
+        from: &Position,
+        to: &Position,
+    ) -> Option<Vec<Position>> {
+        let shift = self.get(to)?;
+        if from == to {
+            return Some(Vec::new());
+        }
+
+        self.offset = self.offset.wrapping_add(shift);
+
+        let grid = Grid::new(map);
+        let mut changed = Vec::new();
+        let mut queue = BinaryHeap::new();
+        let index = self.index(to);
+        self.set(index, 0);
+        queue.push(Reverse((0, to.x, to.y)));
+
+        while let Some(Reverse((cost, x, y))) = queue.pop() {
+            let current = Position::new(x, y);
+            if self.get(&current) != Some(cost) {
+                continue;
+            }
+            changed.push(current.clone());
+
+            for &direction in Direction::ALL.iter() {
+                if let Some(next) = grid.step(&current, direction) {
+                    let cost = cost + step_cost(direction);
+                    let index = self.index(&next);
+                    match self.cost(index) {
+                        Some(old) if old <= cost => {}
+                        _ => {
+                            self.set(index, cost);
+                            queue.push(Reverse((cost, next.x, next.y)));
+                        }
+                    }
+                }
+            }
+        }
+
+        Some(changed)
+    }
+
+    fn index(&self, pos: &Position) -> usize {
+        pos.x as usize + pos.y as usize * self.width as usize
+    }
+
+    fn cost(&self, index: usize) -> Option<usize> {
+        self.costs[index].map(|cost| cost.wrapping_add(self.offset))
+    }
+
+    fn set(&mut self, index: usize, cost: usize) {
+        self.costs[index] = Some(cost.wrapping_sub(self.offset));
+    }
+
+    /// A field which leads away from the sources of this field
+    ///
+    /// Every reachable position becomes a source whose cost is its cost in this field
+    /// multiplied by `-coefficient`, which is then flooded again.  Following the result
+    /// downhill runs away from the original sources, but a coefficient larger than `1`
+    /// lets entities run past the sources to escape a dead end instead of cowering in it.
+    ///
+    /// Costs are offset so that they stay positive; only their differences matter.
+    pub fn flee(&self, map: &Map, coefficient: f64) -> DistanceField {
+        let offset = self.max().unwrap_or(0) as f64 * coefficient;
+        let sources: Vec<_> = self
+            .iter()
+            .map(|(pos, cost)| (pos, (offset - cost as f64 * coefficient).round() as usize))
+            .collect();
+
+        DistanceField::new(map, &sources)
+    }
+}
+
+/// Fields are equal when their costs are, however they are offset
+impl PartialEq for DistanceField {
+    fn eq(&self, other: &Self) -> bool {
+        (self.width, self.height) == (other.width, other.height)
+            && (0..self.costs.len()).all(|i| self.cost(i) == other.cost(i))
+    }
+}
@@ -0,0 +1,324 @@
+//! Compressed first-move databases, which answer path queries on small maps without searching
+//!
+//! For every pair of passable tiles, the database stores the direction of the first step of
+//! a cheapest path from one to the other.  Following the first moves one step at a time walks
+//! the whole path.  The first moves from a tile toward tiles which are close to each other are
+//! usually the same, so each row of the table is stored as runs of equal moves, in the order
+//! the tiles are laid out in the map.
+//!
+//! Building the database floods the map once per passable tile, and the uncompressed table
+//! takes two bytes per pair of tiles, so it is meant for small maps such as arenas.  The
+//! database can be saved along with the [`content_hash`] of its map, and is rejected when
+//! loaded for a map which changed since.
+//!
+//! ```
+//! # use game_lib::map::{Map, Tile};
+//! # use game_lib::Position;
+//! use game_lib::actor::Direction;
+//! use game_lib::nav::FirstMoves;
+//!
+//! let mut map = Map::new(8, 5);
+//! for y in 1..4 {
+//!     for x in 1..7 {
+//!         map[(x, y)] = Tile::FLOOR;
+//!     }
+//! }
+//! map[(4, 2)] = Tile::WALL;
+//! map[(4, 3)] = Tile::WALL;
+//!
+//! let moves = FirstMoves::new(&map);
+//! let (from, to) = (Position::new(2, 3), Position::new(6, 3));
+//! assert_eq!(moves.get(&from, &to), Some(Direction::SouthEast));
+//! assert_eq!(moves.path(&from, &to).map(|path| path.len()), Some(4));
+//!
+//! let mut saved = Vec::new();
+//! moves.save(&mut saved).unwrap();
+//! assert_eq!(FirstMoves::load(&mut saved.as_slice(), &map).unwrap(), moves);
+//!
+//! map[(4, 1)] = Tile::WALL;
+//! assert!(FirstMoves::load(&mut saved.as_slice(), &map).is_err());
+//! ```
+//!
+//! [`content_hash`]: ../../map/struct.Map.html#method.content_hash
+
+use crate::actor::Direction;
+use crate::map::Map;
+use crate::Position;
+
+use super::{step_cost, DistanceField};
+
+use std::io::{self, Read, Write};
+
+/// Marks pairs of tiles without a first move, since they are not connected
+const NO_MOVE: u8 = 8;
+
+/// The bit of a pair of tiles without a first move, next to the bits of the directions
+const NO_MOVE_BIT: u16 = 1 << NO_MOVE;
+
+/// Identifies saved databases
+const MAGIC: &[u8; 4] = b"FMDB";
+
+/// The first move of a cheapest path between every pair of passable tiles of a map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FirstMoves {
+    hash: u64,
+    width: u32,
+    height: u32,
+    nodes: Vec<Option<u32>>,
+    /// Where the runs of each source start, with the end of the last one
+    offsets: Vec<u32>,
+    /// The index of the first target of each run, and the move toward its targets
+    runs: Vec<(u32, u8)>,
+}
+
+impl FirstMoves {
+    /// Build the database of a map, flooding it with `Dijkstra` from every passable tile
+    pub fn new(map: &Map) -> Self {
+        let (width, height, nodes, tiles) = FirstMoves::index(map);
+        let count = tiles.len();
+
+        // The costs are symmetric, so flooding from a target tells every source how to
+        // reach it.  Every first move of a cheapest path is kept, as a bit per direction.
+        let mut table = vec![NO_MOVE_BIT; count * count];
+        for (target, goal) in tiles.iter().enumerate() {
+            let field = DistanceField::new(map, &[(goal.clone(), 0)]);
+
+            for (source, pos) in tiles.iter().enumerate() {
+                let cost = match field.get(pos) {
+                    Some(cost) if source != target => cost,
+                    _ => continue,
+                };
+
+                let mut moves = 0;
+                for (code, &direction) in Direction::ALL.iter().enumerate() {
+                    let next = direction.checked_step(pos.x, pos.y).map(Position::from);
+                    let rest = next.and_then(|next| field.get(&next));
+                    if rest.map(|rest| rest + step_cost(direction)) == Some(cost) {
+                        moves |= 1 << code;
+                    }
+                }
+                table[source * count + target] = moves;
+            }
+        }
+
+        // Runs grow as long as one move is a first move for all of their targets, which
+        // keeps the fewest runs for the order of the targets
+        let mut offsets = Vec::with_capacity(count + 1);
+        let mut runs = Vec::new();
+        for (source, row) in table.chunks(count.max(1)).take(count).enumerate() {
+            offsets.push(runs.len() as u32);
+
+            let mut run: Option<(u32, u16)> = None;
+            for (target, &moves) in row.iter().enumerate() {
+                // The source itself never needs a move
+                if target == source {
+                    continue;
+                }
+
+                run = match run {
+                    Some((first, shared)) if shared & moves != 0 => {
+                        Some((first, shared & moves))
+                    }
+                    Some((first, shared)) => {
+                        runs.push((first, shared.trailing_zeros() as u8));
+                        Some((target as u32, moves))
+                    }
+                    None => Some((target as u32, moves)),
+                };
+            }
+            if let Some((first, shared)) = run {
+                runs.push((first, shared.trailing_zeros() as u8));
+            }
+        }
+        offsets.push(runs.len() as u32);
+
+        FirstMoves { hash: map.content_hash(), width, height, nodes, offsets, runs }
+    }
+
+    /// Test if the database was built for a map with the same content
+    pub fn matches(&self, map: &Map) -> bool {
+        self.hash == map.content_hash()
+    }
+
+    /// The number of runs stored, for every source together
+    pub fn runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// The direction of the first step of a cheapest path between two positions
+    ///
+    /// Returns `None` when either position is blocked, when they are not connected, or
+    /// when they are the same position.
+    pub fn get(&self, from: &Position, to: &Position) -> Option<Direction> {
+        if from == to {
+            return None;
+        }
+
+        let (source, target) = (self.node(from)?, self.node(to)?);
+        let start = self.offsets[source as usize] as usize;
+        let end = self.offsets[source as usize + 1] as usize;
+        let row = &self.runs[start..end];
+
+        let run = match row.binary_search_by_key(&target, |&(first, _)| first) {
+            Ok(run) => run,
+            Err(run) => run.checked_sub(1)?,
+        };
+
+        Direction::ALL.get(row[run].1 as usize).cloned()
+    }
+
+    /// Every step of a cheapest path between two positions, by following the first moves
+    pub fn path(&self, from: &Position, to: &Position) -> Option<Vec<Direction>> {
+        let mut path = Vec::new();
+        let mut current = from.clone();
+
+        while &current != to {
+            let direction = self.get(&current, to)?;
+            current = direction.checked_step(current.x, current.y).map(Position::from)?;
+            path.push(direction);
+        }
+
+        Some(path)
+    }
+
+    /// Write the database along with the hash of its map
+    pub fn save<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        writer.write_all(&self.hash.to_le_bytes())?;
+        writer.write_all(&self.width.to_le_bytes())?;
+        writer.write_all(&self.height.to_le_bytes())?;
+        writer.write_all(&(self.offsets.len() as u32).to_le_bytes())?;
+        writer.write_all(&(self.runs.len() as u32).to_le_bytes())?;
+
+        for offset in &self.offsets {
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        for (first, code) in &self.runs {
+            writer.write_all(&first.to_le_bytes())?;
+            writer.write_all(&[*code])?;
+        }
+
+        Ok(())
+    }
+
+    /// Read a database saved for a map
+    ///
+    /// Fails with `InvalidData` when the data is not a database, or when it was built for a
+    /// map with other content, in which case the database must be built again.
+    ///
+    /// ```
+    /// # use game_lib::map::{Map, Tile};
+    /// # use game_lib::Position;
+    /// use game_lib::nav::FirstMoves;
+    /// use std::io::ErrorKind;
+    ///
+    /// let mut map = Map::new(6, 5);
+    /// for y in 1..4 {
+    ///     for x in 1..5 {
+    ///         map[(x, y)] = Tile::FLOOR;
+    ///     }
+    /// }
+    /// let mut saved = Vec::new();
+    /// FirstMoves::new(&map).save(&mut saved).unwrap();
+    ///
+    /// // A tile which still blocks keeps the content hash, and the database
+    /// map[(0, 0)] = Tile::BLOCK;
+    /// assert!(FirstMoves::load(&mut saved.as_slice(), &map).is_ok());
+    ///
+    /// // A new wall changes the hash, so the database is rejected and built again
+    /// map[(2, 2)] = Tile::WALL;
+    /// let err = FirstMoves::load(&mut saved.as_slice(), &map).unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::InvalidData);
+    ///
+    /// let moves = FirstMoves::new(&map);
+    /// assert!(moves.matches(&map));
+    /// saved.clear();
+    /// moves.save(&mut saved).unwrap();
+    /// let loaded = FirstMoves::load(&mut saved.as_slice(), &map).unwrap();
+    /// assert_eq!(loaded, moves);
+    /// let (from, to) = (Position::new(1, 2), Position::new(3, 2));
+    /// assert_eq!(loaded.path(&from, &to).map(|path| path.len()), Some(2));
+    /// ```
+    pub fn load<R: Read>(reader: &mut R, map: &Map) -> io::Result<Self> {
+        let invalid = |message| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a first move database"));
+        }
+
+        let hash = read_u64(reader)?;
+        if hash != map.content_hash() {
+            return Err(invalid("the map changed since the database was built"));
+        }
+
+        let (width, height, nodes, tiles) = FirstMoves::index(map);
+        if (read_u32(reader)?, read_u32(reader)?) != (width, height) {
+            return Err(invalid("the map changed since the database was built"));
+        }
+
+        let offset_count = read_u32(reader)? as usize;
+        let run_count = read_u32(reader)? as usize;
+        if offset_count != tiles.len() + 1 {
+            return Err(invalid("the database does not cover the tiles of the map"));
+        }
+
+        let offsets =
+            (0..offset_count).map(|_| read_u32(reader)).collect::<io::Result<Vec<_>>>()?;
+        let runs = (0..run_count)
+            .map(|_| {
+                let first = read_u32(reader)?;
+                let mut code = [0];
+                reader.read_exact(&mut code)?;
+                Ok((first, code[0]))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let sorted = offsets.windows(2).all(|pair| pair[0] <= pair[1]);
+        if !sorted || offsets.last().map(|&end| end as usize) != Some(run_count) {
+            return Err(invalid("the runs of the database are corrupted"));
+        }
+
+        Ok(FirstMoves { hash, width, height, nodes, offsets, runs })
+    }
+
+    /// Number the passable tiles of a map in the order they are laid out
+    fn index(map: &Map) -> (u32, u32, Vec<Option<u32>>, Vec<Position>) {
+        let (width, height) = map.dimensions();
+        let mut nodes = vec![None; width as usize * height as usize];
+        let mut tiles = Vec::new();
+
+        for y in 0..height {
+            for x in 0..width {
+                let pos = Position::new(x, y);
+                if map.is_passable(&pos) {
+                    nodes[x as usize + y as usize * width as usize] = Some(tiles.len() as u32);
+                    tiles.push(pos);
+                }
+            }
+        }
+
+        (width, height, nodes, tiles)
+    }
+
+    fn node(&self, pos: &Position) -> Option<u32> {
+        if pos.x < self.width && pos.y < self.height {
+            self.nodes[pos.x as usize + pos.y as usize * self.width as usize]
+        } else {
+            None
+        }
+    }
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
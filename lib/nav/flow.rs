@@ -0,0 +1,107 @@
+//! Flow fields, which store the next step toward a single goal for every tile
+//!
+//! A flow field is built once per goal and shared by every entity heading there, which
+//! then only has to look up the direction under its feet each turn.
+//!
+//! ```
+//! # use game_lib::map::{Map, Tile};
+//! # use game_lib::Position;
+//! use game_lib::actor::Direction;
+//! use game_lib::nav::FlowField;
+//!
+//! let mut map = Map::new(10, 3);
+//! for x in 1..9 {
+//!     map[(x, 1)] = Tile::FLOOR;
+//! }
+//!
+//! let mut flow = FlowField::new(&map, Position::new(1, 1));
+//! assert_eq!(flow.get(&Position::new(5, 1)), Some(Direction::West));
+//! assert_eq!(flow.get(&Position::new(1, 1)), None);
+//!
+//! // The player walked past the monster, so the field is repaired around the new goal
+//! flow.move_goal(&map, Position::new(7, 1));
+//! assert_eq!(flow.get(&Position::new(5, 1)), Some(Direction::East));
+//! assert_eq!(flow.get(&Position::new(8, 1)), Some(Direction::West));
+//! assert_eq!(flow.field().get(&Position::new(1, 1)), Some(12));
+//! ```
+
+use crate::actor::Direction;
+use crate::map::Map;
+use crate::Position;
+
+use super::DistanceField;
+
+/// The direction of the cheapest step toward a goal from every reachable tile
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FlowField {
+    goal: Position,
+    field: DistanceField,
+    directions: Vec<Option<Direction>>,
+}
+
+impl FlowField {
+    pub fn new(map: &Map, goal: Position) -> Self {
+        let field = DistanceField::new(map, &[(goal.clone(), 0)]);
+        let (width, height) = field.dimensions();
+
+        let mut directions = vec![None; width as usize * height as usize];
+        for (pos, _) in field.iter() {
+            directions[pos.x as usize + pos.y as usize * width as usize] =
+                field.downhill(&pos);
+        }
+
+        FlowField { goal, field, directions }
+    }
+
+    pub fn goal(&self) -> &Position {
+        &self.goal
+    }
+
+    /// The distance field the directions were derived from
+    pub fn field(&self) -> &DistanceField {
+        &self.field
+    }
+
+    /// The direction to walk from a position, or `None` at the goal or when it is unreachable
+    #[inline]
+    pub fn get(&self, pos: &Position) -> Option<Direction> {
+        let (width, height) = self.field.dimensions();
+        if pos.x < width && pos.y < height {
+            self.directions[pos.x as usize + pos.y as usize * width as usize]
+        } else {
+            None
+        }
+    }
+
+    /// Point the field at a new goal
+    ///
+    /// Only the tiles which get closer to the new goal, and their neighbours, are updated,
+    /// so following a goal which moves a few tiles per turn is much cheaper than building a
+    /// new field.  A goal which cannot be reached from the previous one rebuilds the field.
+    pub fn move_goal(&mut self, map: &Map, goal: Position) {
+        match self.field.move_source(map, &self.goal, &goal) {
+            Some(changed) => {
+                let previous = std::mem::replace(&mut self.goal, goal);
+                self.update(&previous);
+
+                for pos in changed {
+                    self.update(&pos);
+                    for &direction in Direction::ALL.iter() {
+                        if let Some((x, y)) = direction.checked_step(pos.x, pos.y) {
+                            self.update(&Position::new(x, y));
+                        }
+                    }
+                }
+            }
+            None => *self = FlowField::new(map, goal),
+        }
+    }
+
+    fn update(&mut self, pos: &Position) {
+        let (width, height) = self.field.dimensions();
+        if pos.x < width && pos.y < height {
+            self.directions[pos.x as usize + pos.y as usize * width as usize] =
+                self.field.downhill(pos);
+        }
+    }
+}
@@ -0,0 +1,477 @@
+//! Hierarchical pathfinding, also known as HPA*
+//!
+//! The map is divided into square clusters.  Wherever an entity can step from one cluster
+//! into its neighbour, the border has an _entrance_, and the cost of walking between the
+//! entrances of a cluster is computed once, when the hierarchy is built.  A query then only
+//! searches this small abstract graph of entrances, instead of every tile between the start
+//! and the goal, and the tiles of each leg of the journey are planned when they are needed.
+//!
+//! The paths found are close to, but not always, the cheapest ones, because they have to
+//! pass through the entrances.
+//!
+//! ```
+//! # use game_lib::map::{Map, Tile};
+//! # use game_lib::Position;
+//! use game_lib::nav::Hierarchy;
+//!
+//! // Two caves joined by a door
+//! let mut map = Map::new(32, 12);
+//! for y in 1..11 {
+//!     for x in 1..31 {
+//!         map[(x, y)] = Tile::FLOOR;
+//!     }
+//!     map[(15, y)] = Tile::WALL;
+//! }
+//! map[(15, 9)] = Tile::FLOOR;
+//!
+//! let mut hierarchy = Hierarchy::new(&map, 8);
+//! let (start, goal) = (Position::new(2, 2), Position::new(29, 2));
+//!
+//! let mut path = hierarchy.plan(&map, &start, &goal).unwrap();
+//! assert_eq!(path.waypoints().first(), Some(&start));
+//! assert_eq!(path.waypoints().last(), Some(&goal));
+//!
+//! // The legs are only walked tile by tile when the entity gets to them
+//! let first_leg = path.refine(&hierarchy, &map).unwrap();
+//! assert_eq!(first_leg.last(), path.waypoints().get(1));
+//!
+//! let tiles = path.into_tiles(&hierarchy, &map).unwrap();
+//! assert!(tiles.contains(&Position::new(15, 9)));
+//! assert_eq!(tiles.last(), Some(&goal));
+//!
+//! // Opening a second door only rebuilds the clusters next to it
+//! let detour = hierarchy.plan(&map, &start, &goal).unwrap().cost();
+//! map[(15, 2)] = Tile::FLOOR;
+//! hierarchy.update(&map, &Position::new(15, 2));
+//! assert!(hierarchy.plan(&map, &start, &goal).unwrap().cost() < detour);
+//! ```
+
+use crate::actor::Direction;
+use crate::map::Map;
+use crate::path::astar::AStar;
+use crate::path::dijkstra::Dijkstra;
+use crate::path::{Diagnostics, Optimizer, PathFindingErr, PathResult, Target};
+use crate::{Position, Rect};
+
+use super::{octile, Grid, Octile, DIAGONAL_COST, STRAIGHT_COST};
+
+use fnv::FnvHashMap;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::BinaryHeap;
+
+/// Entrances narrower than this get a single transition in their middle, and wider ones get
+/// a transition at both ends
+const WIDE_ENTRANCE: usize = 6;
+
+/// An abstract graph of the entrances between the clusters of a map
+#[derive(Clone, Debug)]
+pub struct Hierarchy {
+    size: u32,
+    width: u32,
+    height: u32,
+    columns: u32,
+    rows: u32,
+    /// Pairs of tiles to step between neighbouring clusters, by the pair of clusters
+    ///
+    /// The first cluster of the pair is always the one with the lower index, and the first
+    /// tile of a transition lies in it.
+    transitions: FnvHashMap<(usize, usize), Vec<(Position, Position)>>,
+    /// The entrance tiles of every cluster
+    entrances: Vec<Vec<Position>>,
+    /// Edges of the abstract graph with their cost, both inside and between clusters
+    edges: FnvHashMap<Position, Vec<(Position, usize)>>,
+}
+
+impl Hierarchy {
+    /// Divide a map into clusters of `size` by `size` tiles and connect their entrances
+    pub fn new(map: &Map, size: u32) -> Self {
+        let size = size.max(2);
+        let (width, height) = map.dimensions();
+        let columns = width / size + u32::from(width % size != 0);
+        let rows = height / size + u32::from(height % size != 0);
+
+        let mut hierarchy = Hierarchy {
+            size,
+            width,
+            height,
+            columns,
+            rows,
+            transitions: FnvHashMap::default(),
+            entrances: vec![Vec::new(); (columns * rows) as usize],
+            edges: FnvHashMap::default(),
+        };
+
+        for cluster in 0..hierarchy.entrances.len() {
+            for other in hierarchy.neighbours(cluster).filter(|&other| other > cluster) {
+                let transitions = hierarchy.scan(map, cluster, other);
+                hierarchy.transitions.insert((cluster, other), transitions);
+            }
+        }
+
+        for cluster in 0..hierarchy.entrances.len() {
+            hierarchy.connect(map, cluster);
+        }
+
+        hierarchy
+    }
+
+    /// The width and height of the clusters, in tiles
+    pub fn cluster_size(&self) -> u32 {
+        self.size
+    }
+
+    /// The index of the cluster which contains a position
+    pub fn cluster(&self, pos: &Position) -> Option<usize> {
+        if pos.x < self.width && pos.y < self.height {
+            Some((pos.y / self.size * self.columns + pos.x / self.size) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// The tiles covered by a cluster, as an area of `w` by `h` tiles
+    pub fn area(&self, cluster: usize) -> Rect {
+        let x = cluster as u32 % self.columns * self.size;
+        let y = cluster as u32 / self.columns * self.size;
+        Rect::new((x, y), self.size.min(self.width - x), self.size.min(self.height - y))
+    }
+
+    /// The entrance tiles of a cluster
+    pub fn entrances(&self, cluster: usize) -> &[Position] {
+        &self.entrances[cluster]
+    }
+
+    /// Update the hierarchy after the tile at `pos` changed
+    ///
+    /// Only the cluster of the tile is rebuilt, along with the neighbouring clusters whose
+    /// entrances changed.
+    pub fn update(&mut self, map: &Map, pos: &Position) {
+        let cluster = match self.cluster(pos) {
+            Some(cluster) => cluster,
+            None => return,
+        };
+
+        let mut touched = vec![cluster];
+        for other in self.neighbours(cluster).collect::<Vec<_>>() {
+            let key = (cluster.min(other), cluster.max(other));
+            let transitions = self.scan(map, key.0, key.1);
+            if self.transitions.get(&key) != Some(&transitions) {
+                self.transitions.insert(key, transitions);
+                touched.push(other);
+            }
+        }
+
+        for cluster in touched {
+            self.connect(map, cluster);
+        }
+    }
+
+    /// Plan through the abstract graph from `start` to `goal`
+    ///
+    /// The start and goal are connected to the entrances of their clusters, and the cheapest
+    /// sequence of entrances between them is searched.  The tiles of the path are found
+    /// later, one leg at a time, with [`HierarchicalPath::refine`].
+    ///
+    /// [`HierarchicalPath::refine`]: struct.HierarchicalPath.html#method.refine
+    pub fn plan(
+        &self,
+        map: &Map,
+        start: &Position,
+        goal: &Position,
+    ) -> Result<HierarchicalPath, PathFindingErr> {
+        if !map.is_passable(start) || self.cluster(start).is_none() {
+            return Err(PathFindingErr::StartInvalid(Diagnostics::default()));
+        }
+        Target::At(goal.clone()).validate(map)?;
+
+        let start_cluster = self.cluster(start).unwrap_or(0);
+        let goal_cluster = self.cluster(goal).unwrap_or(0);
+
+        // Connect the start and the goal to the entrances of their clusters
+        let from_start = self.flood(map, start, start_cluster);
+        let mut start_edges: Vec<(Position, usize)> = self.entrances[start_cluster]
+            .iter()
+            .filter_map(|entrance| {
+                from_start.cost(entrance).map(|cost| (entrance.clone(), cost))
+            })
+            .collect();
+        if let Some(cost) = from_start.cost(goal) {
+            start_edges.push((goal.clone(), cost));
+        }
+
+        let from_goal = self.flood(map, goal, goal_cluster);
+        let goal_edges: FnvHashMap<&Position, usize> = self.entrances[goal_cluster]
+            .iter()
+            .filter_map(|entrance| from_goal.cost(entrance).map(|cost| (entrance, cost)))
+            .collect();
+
+        // A* over the entrances, where the nodes are stored by index to be ordered
+        let mut nodes: Vec<(Position, usize, Option<usize>)> = vec![(start.clone(), 0, None)];
+        let mut index: FnvHashMap<Position, usize> = FnvHashMap::default();
+        let mut closed = vec![false];
+        let mut queue = BinaryHeap::new();
+        let mut diagnostics = Diagnostics::default();
+
+        index.insert(start.clone(), 0);
+        queue.push(Reverse((octile(start, goal), 0)));
+
+        while let Some(Reverse((_, current))) = queue.pop() {
+            if closed[current] {
+                continue;
+            }
+            closed[current] = true;
+            diagnostics.expansions += 1;
+
+            let (pos, g, _) = nodes[current].clone();
+            if &pos == goal {
+                return Ok(HierarchicalPath::unwind(&nodes, current));
+            }
+
+            let inside = self.edges.get(&pos).into_iter().flatten().cloned();
+            let from_start = start_edges.iter().filter(|_| current == 0).cloned();
+            let to_goal = goal_edges.get(&pos).map(|&cost| (goal.clone(), cost));
+
+            for (next, cost) in inside.chain(from_start).chain(to_goal) {
+                let g = g + cost;
+                let next_index = match index.entry(next.clone()) {
+                    Entry::Occupied(entry) => {
+                        let i = *entry.get();
+                        if nodes[i].1 <= g {
+                            continue;
+                        }
+                        nodes[i].1 = g;
+                        nodes[i].2 = Some(current);
+                        closed[i] = false;
+                        i
+                    }
+                    Entry::Vacant(entry) => {
+                        entry.insert(nodes.len());
+                        nodes.push((next.clone(), g, Some(current)));
+                        closed.push(false);
+                        nodes.len() - 1
+                    }
+                };
+
+                queue.push(Reverse((g + octile(&next, goal), next_index)));
+            }
+        }
+
+        diagnostics.closed = nodes.len();
+        Err(PathFindingErr::Unreachable(diagnostics))
+    }
+
+    /// The clusters around a cluster
+    fn neighbours(&self, cluster: usize) -> impl Iterator<Item = usize> {
+        let (columns, rows) = (i64::from(self.columns), i64::from(self.rows));
+        let (x, y) = (cluster as i64 % columns, cluster as i64 / columns);
+
+        Direction::ALL
+            .iter()
+            .map(|direction| direction.offset())
+            .map(move |(dx, dy)| (x + dx, y + dy))
+            .filter(move |&(x, y)| x >= 0 && y >= 0 && x < columns && y < rows)
+            .map(move |(x, y)| (y * columns + x) as usize)
+    }
+
+    /// Find the transitions between two neighbouring clusters, where `a < b`
+    fn scan(&self, map: &Map, a: usize, b: usize) -> Vec<(Position, Position)> {
+        let (area_a, area_b) = (self.area(a), self.area(b));
+        let (right, bottom) = (area_a.pos.x + area_a.w, area_a.pos.y + area_a.h);
+        let open = |pos: &Position| map.is_passable(pos);
+
+        let border: Vec<(Position, Position)> = if area_b.pos.y == area_a.pos.y {
+            // b is to the right of a
+            (area_a.pos.y..bottom)
+                .map(|y| (Position::new(right - 1, y), Position::new(right, y)))
+                .collect()
+        } else if area_b.pos.x == area_a.pos.x {
+            // b is below a
+            (area_a.pos.x..right)
+                .map(|x| (Position::new(x, bottom - 1), Position::new(x, bottom)))
+                .collect()
+        } else {
+            // b is diagonal to a, so the clusters only touch at a corner
+            let (from, to) = if area_b.pos.x > area_a.pos.x {
+                (Position::new(right - 1, bottom - 1), Position::new(right, bottom))
+            } else {
+                (
+                    Position::new(area_a.pos.x, bottom - 1),
+                    Position::new(area_b.pos.x + area_b.w - 1, bottom),
+                )
+            };
+            return if open(&from) && open(&to) { vec![(from, to)] } else { Vec::new() };
+        };
+
+        let crossing: Vec<bool> =
+            border.iter().map(|(from, to)| open(from) && open(to)).collect();
+        let mut transitions = Vec::new();
+
+        // Runs of tiles which can be crossed straight over the border
+        let mut i = 0;
+        while i < border.len() {
+            if !crossing[i] {
+                i += 1;
+                continue;
+            }
+
+            let first = i;
+            while i < border.len() && crossing[i] {
+                i += 1;
+            }
+            let last = i - 1;
+
+            if last - first + 1 < WIDE_ENTRANCE {
+                transitions.push(border[(first + last) / 2].clone());
+            } else {
+                transitions.push(border[first].clone());
+                transitions.push(border[last].clone());
+            }
+        }
+
+        // Diagonal steps over the border, which are only needed when neither tile is next to
+        // a straight crossing
+        for i in 1..border.len() {
+            if crossing[i - 1] || crossing[i] {
+                continue;
+            }
+
+            let ((from_0, to_0), (from_1, to_1)) = (&border[i - 1], &border[i]);
+            if open(from_0) && open(to_1) {
+                transitions.push((from_0.clone(), to_1.clone()));
+            }
+            if open(from_1) && open(to_0) {
+                transitions.push((from_1.clone(), to_0.clone()));
+            }
+        }
+
+        transitions
+    }
+
+    /// Rebuild the entrances of a cluster and the edges leaving them
+    fn connect(&mut self, map: &Map, cluster: usize) {
+        for entrance in &self.entrances[cluster] {
+            self.edges.remove(entrance);
+        }
+
+        // Every transition with its tile in this cluster first
+        let mut crossings: Vec<(Position, Position)> = Vec::new();
+        for other in self.neighbours(cluster) {
+            let key = (cluster.min(other), cluster.max(other));
+            for (a, b) in self.transitions.get(&key).into_iter().flatten() {
+                crossings.push(if key.0 == cluster {
+                    (a.clone(), b.clone())
+                } else {
+                    (b.clone(), a.clone())
+                });
+            }
+        }
+
+        let mut entrances: Vec<Position> = Vec::new();
+        for (entrance, _) in &crossings {
+            if !entrances.contains(entrance) {
+                entrances.push(entrance.clone());
+            }
+        }
+
+        for entrance in &entrances {
+            let flood = self.flood(map, entrance, cluster);
+            let inside = entrances
+                .iter()
+                .filter(|&other| other != entrance)
+                .filter_map(|other| flood.cost(other).map(|cost| (other.clone(), cost)));
+
+            let across =
+                crossings.iter().filter(|(from, _)| from == entrance).map(|(from, to)| {
+                    let diagonal = from.x != to.x && from.y != to.y;
+                    (to.clone(), if diagonal { DIAGONAL_COST } else { STRAIGHT_COST })
+                });
+
+            self.edges.insert(entrance.clone(), inside.chain(across).collect());
+        }
+
+        self.entrances[cluster] = entrances;
+    }
+
+    /// The cost from a position to every tile of its cluster, without leaving the cluster
+    fn flood<'a>(&self, map: &'a Map, from: &Position, cluster: usize) -> Dijkstra<Grid<'a>> {
+        let mut grid = Grid::new(map).with_area(self.area(cluster));
+        let mut dijkstra = Dijkstra::default();
+        dijkstra.flood(&mut grid, vec![(from.clone(), 0)], &mut Octile);
+        dijkstra
+    }
+}
+
+/// A path through the entrances of a [`Hierarchy`], refined into tiles one leg at a time
+///
+/// [`Hierarchy`]: struct.Hierarchy.html
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HierarchicalPath {
+    waypoints: Vec<Position>,
+    cost: usize,
+    next: usize,
+}
+
+impl HierarchicalPath {
+    fn unwind(nodes: &[(Position, usize, Option<usize>)], goal: usize) -> Self {
+        let mut waypoints = Vec::new();
+        let mut current = Some(goal);
+        while let Some(i) = current {
+            waypoints.push(nodes[i].0.clone());
+            current = nodes[i].2;
+        }
+        waypoints.reverse();
+
+        HierarchicalPath { waypoints, cost: nodes[goal].1, next: 0 }
+    }
+
+    /// The start, the entrances passed through, and the goal
+    pub fn waypoints(&self) -> &[Position] {
+        &self.waypoints
+    }
+
+    /// The cost of walking the path
+    pub fn cost(&self) -> usize {
+        self.cost
+    }
+
+    /// Test if every leg of the path was refined
+    pub fn is_done(&self) -> bool {
+        self.next + 1 >= self.waypoints.len()
+    }
+
+    /// Plan the tiles of the next leg of the path, from one waypoint to the next
+    ///
+    /// The tiles are returned without the waypoint the leg starts from, so the legs can be
+    /// appended to each other.  Returns `None` once every leg was refined, or when the map
+    /// changed so that the leg can no longer be walked, in which case the path should be
+    /// planned again.
+    pub fn refine(&mut self, hierarchy: &Hierarchy, map: &Map) -> Option<Vec<Position>> {
+        let from = self.waypoints.get(self.next)?.clone();
+        let to = self.waypoints.get(self.next + 1)?.clone();
+        self.next += 1;
+
+        let cluster = hierarchy.cluster(&from)?;
+        if hierarchy.cluster(&to) != Some(cluster) {
+            // Stepping over the border of a cluster
+            return Some(vec![to]);
+        }
+
+        let mut grid = Grid::new(map).with_area(hierarchy.area(cluster));
+        match AStar::new().optimize(&mut grid, &from, &Target::At(to), &mut Octile) {
+            PathResult::Final(trajectory) => {
+                Some(trajectory.trajectory.into_iter().skip(1).map(|(pos, _)| pos).collect())
+            }
+            _ => None,
+        }
+    }
+
+    /// Refine the remaining legs into every tile from the current waypoint to the goal
+    pub fn into_tiles(mut self, hierarchy: &Hierarchy, map: &Map) -> Option<Vec<Position>> {
+        let mut tiles = vec![self.waypoints.get(self.next)?.clone()];
+        while !self.is_done() {
+            tiles.extend(self.refine(hierarchy, map)?);
+        }
+        Some(tiles)
+    }
+}
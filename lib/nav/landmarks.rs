@@ -0,0 +1,137 @@
+//! Landmark distance tables for the ALT heuristic
+//!
+//! A few _landmarks_ are picked far apart on the map, and the cost from each landmark to
+//! every tile is stored.  By the triangle inequality, the cost between two tiles is at least
+//! the difference of their costs from any landmark, which is a far better estimate than the
+//! distance as the crow flies when walls force paths around.
+//!
+//! ```
+//! # use game_lib::map::{Map, Tile};
+//! # use game_lib::Position;
+//! use game_lib::nav::{octile, Landmarks};
+//!
+//! // A corridor which doubles back on itself
+//! let mut map = Map::new(12, 5);
+//! for x in 1..11 {
+//!     map[(x, 1)] = Tile::FLOOR;
+//!     map[(x, 3)] = Tile::FLOOR;
+//! }
+//! map[(10, 2)] = Tile::FLOOR;
+//!
+//! let landmarks = Landmarks::new(&map, 2);
+//! let (a, b) = (Position::new(1, 1), Position::new(1, 3));
+//!
+//! assert_eq!(octile(&a, &b), 4);
+//! assert_eq!(landmarks.distance(&a, &b), 38);
+//! ```
+
+use crate::map::Map;
+use crate::path::Target;
+use crate::Position;
+
+use super::DistanceField;
+
+/// Distance tables from landmarks spread over a map
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Landmarks {
+    landmarks: Vec<Position>,
+    fields: Vec<DistanceField>,
+}
+
+impl Landmarks {
+    /// Pick up to `count` landmarks on a map with farthest-point selection
+    ///
+    /// The first landmark is the tile farthest from the first passable tile of the map, and
+    /// every following landmark is the tile farthest from all the landmarks picked so far.
+    /// Tiles which no landmark reaches yet are the farthest of all, so every component of
+    /// the map gets a landmark before any component gets a second one.
+    pub fn new(map: &Map, count: usize) -> Self {
+        let (width, height) = map.dimensions();
+        let passable: Vec<Position> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| Position::new(x, y)))
+            .filter(|pos| map.is_passable(pos))
+            .collect();
+
+        let mut landmarks = Vec::new();
+        let mut fields: Vec<DistanceField> = Vec::new();
+
+        let mut nearest: Vec<Option<usize>> = match passable.first() {
+            Some(first) => {
+                let field = DistanceField::new(map, &[(first.clone(), 0)]);
+                passable.iter().map(|pos| field.get(pos)).collect()
+            }
+            None => Vec::new(),
+        };
+
+        while landmarks.len() < count {
+            // Unreached tiles first, then the tile with the highest cost from any landmark
+            let farthest = nearest
+                .iter()
+                .enumerate()
+                .max_by_key(|&(_, cost)| (cost.is_none(), *cost))
+                .map(|(i, _)| i);
+
+            let next = match farthest {
+                Some(next) if nearest[next] != Some(0) || landmarks.is_empty() => next,
+                _ => break,
+            };
+
+            let field = DistanceField::new(map, &[(passable[next].clone(), 0)]);
+            for (pos, cost) in passable.iter().zip(nearest.iter_mut()) {
+                if let Some(new) = field.get(pos) {
+                    *cost = Some(cost.map_or(new, |old| old.min(new)));
+                }
+            }
+
+            landmarks.push(passable[next].clone());
+            fields.push(field);
+        }
+
+        Landmarks { landmarks, fields }
+    }
+
+    /// The positions of the landmarks
+    pub fn positions(&self) -> &[Position] {
+        &self.landmarks
+    }
+
+    /// A lower bound of the cost of the cheapest path between two positions
+    pub fn distance(&self, from: &Position, to: &Position) -> usize {
+        self.fields
+            .iter()
+            .filter_map(|field| {
+                let (from, to) = (field.get(from)?, field.get(to)?);
+                Some(from.max(to) - from.min(to))
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// A lower bound of the cost of the cheapest path from a position to a target
+    ///
+    /// The costs of the positions of the target from a landmark span an interval, and the
+    /// path must at least cover the difference between the cost of `pos` and that interval.
+    /// Every position of the target is visited for each landmark, so large regions are slow
+    /// to estimate.  Predicates are estimated at `0`.
+    pub fn estimate(&self, pos: &Position, target: &Target) -> usize {
+        let (width, height) = match self.fields.first() {
+            Some(field) => field.dimensions(),
+            None => return 0,
+        };
+
+        self.fields
+            .iter()
+            .filter_map(|field| {
+                let cost = field.get(pos)?;
+                let mut costs = target.positions(width, height)?.filter_map(|g| field.get(&g));
+
+                let first = costs.next()?;
+                let (low, high) =
+                    costs.fold((first, first), |(low, high), c| (low.min(c), high.max(c)));
+
+                Some(low.saturating_sub(cost).max(cost.saturating_sub(high)))
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
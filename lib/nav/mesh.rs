@@ -0,0 +1,463 @@
+//! Navigation meshes, which cover the passable tiles of a map with convex regions
+//!
+//! Within a convex region an entity can walk in a straight line between any two points, so
+//! planning only has to decide which regions to cross, and a map of thousands of tiles is
+//! covered by a few hundred regions.  The regions are rectangles of tiles, joined by
+//! _portals_ where their sides touch.
+//!
+//! The trajectory through the regions is then pulled tight like a string with the funnel
+//! algorithm, which gives entities that are not locked to the grid straight paths which only
+//! turn at the corners of walls.
+//!
+//! ```
+//! # use game_lib::map::{Map, Tile};
+//! use game_lib::nav::NavMesh;
+//!
+//! // A room with a wall in the middle, which has to be walked around
+//! let mut map = Map::new(10, 10);
+//! for y in 1..9 {
+//!     for x in 1..9 {
+//!         map[(x, y)] = Tile::FLOOR;
+//!     }
+//! }
+//! for y in 1..7 {
+//!     map[(5, y)] = Tile::WALL;
+//! }
+//!
+//! let mesh = NavMesh::new(&map);
+//! let path = mesh.find_path((2.5, 2.5), (7.5, 2.5)).unwrap();
+//!
+//! // The path only turns at the two corners at the end of the wall
+//! assert_eq!(path, vec![(2.5, 2.5), (5.0, 7.0), (6.0, 7.0), (7.5, 2.5)]);
+//! ```
+
+use crate::kinematic::COST_SCALE;
+use crate::map::Map;
+use crate::path::astar::AStar;
+use crate::path::{
+    Diagnostics, HeuristicModel, Model, Optimizer, PathFindingErr, PathResult, Sampler, State,
+};
+use crate::{Position, Rect};
+
+/// A point in continuous space, where the tile at `(x, y)` covers `(x, y)` to `(x + 1, y + 1)`
+pub type Point = (f64, f64);
+
+/// Where the side of a region touches another region
+#[derive(Debug, Clone, PartialEq)]
+pub struct Portal {
+    /// The region on the other side
+    pub region: usize,
+    /// The ends of the segment shared by both regions
+    pub ends: (Point, Point),
+}
+
+impl Portal {
+    pub fn midpoint(&self) -> Point {
+        let ((x0, y0), (x1, y1)) = self.ends;
+        ((x0 + x1) / 2.0, (y0 + y1) / 2.0)
+    }
+}
+
+/// Convex regions covering the passable tiles of a map, and the portals between them
+#[derive(Debug, Clone, PartialEq)]
+pub struct NavMesh {
+    width: u32,
+    height: u32,
+    regions: Vec<Rect>,
+    labels: Vec<Option<usize>>,
+    portals: Vec<Vec<Portal>>,
+}
+
+impl NavMesh {
+    /// Cover the passable tiles of a map with rectangles
+    ///
+    /// Rectangles are grown greedily, first along a row and then down as many rows as
+    /// possible, from the first tile which is not covered yet.
+    pub fn new(map: &Map) -> Self {
+        let (width, height) = map.dimensions();
+        let mut mesh = NavMesh {
+            width,
+            height,
+            regions: Vec::new(),
+            labels: vec![None; width as usize * height as usize],
+            portals: Vec::new(),
+        };
+
+        let free = |mesh: &NavMesh, x: u32, y: u32| {
+            let pos = Position::new(x, y);
+            map.is_passable(&pos) && mesh.labels[mesh.index(&pos)].is_none()
+        };
+
+        for y in 0..height {
+            for x in 0..width {
+                if !free(&mesh, x, y) {
+                    continue;
+                }
+
+                let mut w = 1;
+                while x + w < width && free(&mesh, x + w, y) {
+                    w += 1;
+                }
+                let mut h = 1;
+                while y + h < height && (x..x + w).all(|x| free(&mesh, x, y + h)) {
+                    h += 1;
+                }
+
+                let region = mesh.regions.len();
+                for y in y..y + h {
+                    for x in x..x + w {
+                        let index = mesh.index(&Position::new(x, y));
+                        mesh.labels[index] = Some(region);
+                    }
+                }
+                mesh.regions.push(Rect::new((x, y), w, h));
+            }
+        }
+
+        mesh.portals =
+            (0..mesh.regions.len()).map(|region| mesh.find_portals(region)).collect();
+        mesh
+    }
+
+    /// The regions, as areas of `w` by `h` tiles
+    pub fn regions(&self) -> &[Rect] {
+        &self.regions
+    }
+
+    /// The portals leading out of a region
+    pub fn portals(&self, region: usize) -> &[Portal] {
+        &self.portals[region]
+    }
+
+    /// The region which contains a point, if the point is on a passable tile
+    pub fn region(&self, (x, y): Point) -> Option<usize> {
+        if x < 0.0 || y < 0.0 || x >= f64::from(self.width) || y >= f64::from(self.height) {
+            return None;
+        }
+
+        self.labels[self.index(&Position::new(x as u32, y as u32))]
+    }
+
+    /// Plan a straight-line path between two points
+    ///
+    /// The regions to cross are planned with A* from portal to portal, and the path through
+    /// them is pulled tight with [`funnel`].  The path starts at `start` and ends at `goal`.
+    ///
+    /// [`funnel`]: fn.funnel.html
+    pub fn find_path(&self, start: Point, goal: Point) -> Result<Vec<Point>, PathFindingErr> {
+        let mut graph = RegionGraph::new(self);
+        let region = match self.region(start) {
+            Some(region) => region,
+            None => return Err(PathFindingErr::StartInvalid(Diagnostics::default())),
+        };
+
+        let start_state = Crossing { region, point: start };
+        let mut sampler = PortalSampler::new(self);
+        let trajectory =
+            match AStar::new().optimize(&mut graph, &start_state, &goal, &mut sampler) {
+                PathResult::Final(trajectory) => trajectory,
+                PathResult::Err(err) => return Err(err),
+                _ => return Err(PathFindingErr::Unreachable(Diagnostics::default())),
+            };
+
+        let portals: Vec<(Point, Point)> = trajectory
+            .trajectory
+            .windows(2)
+            .map(|step| {
+                let (from, _) = &step[0];
+                let (_, control) = &step[1];
+                self.oriented(from.region, &self.portals[from.region][*control])
+            })
+            .collect();
+
+        Ok(funnel(start, &portals, goal))
+    }
+
+    /// The ends of a portal as `(left, right)`, when walking through it out of a region
+    fn oriented(&self, region: usize, portal: &Portal) -> (Point, Point) {
+        let (from, to) = (center(&self.regions[region]), center(&self.regions[portal.region]));
+        let direction = (to.0 - from.0, to.1 - from.1);
+        let (a, b) = portal.ends;
+
+        if cross(direction, (b.0 - a.0, b.1 - a.1)) < 0.0 {
+            (a, b)
+        } else {
+            (b, a)
+        }
+    }
+
+    /// Follow the four sides of a region to find the regions it touches
+    fn find_portals(&self, region: usize) -> Vec<Portal> {
+        let rect = &self.regions[region];
+        let (left, top) = (rect.pos.x, rect.pos.y);
+        let (right, bottom) = (left + rect.w, top + rect.h);
+        let mut portals = Vec::new();
+
+        // The tiles just outside of each side, with the line of the side
+        let mut sides: Vec<(Vec<Position>, bool, u32)> = Vec::new();
+        if left > 0 {
+            sides.push((
+                (top..bottom).map(|y| Position::new(left - 1, y)).collect(),
+                true,
+                left,
+            ));
+        }
+        sides.push(((top..bottom).map(|y| Position::new(right, y)).collect(), true, right));
+        if top > 0 {
+            sides.push((
+                (left..right).map(|x| Position::new(x, top - 1)).collect(),
+                false,
+                top,
+            ));
+        }
+        sides.push(((left..right).map(|x| Position::new(x, bottom)).collect(), false, bottom));
+
+        for (tiles, vertical, line) in sides {
+            let labels: Vec<Option<usize>> = tiles
+                .iter()
+                .map(|pos| {
+                    if pos.x < self.width && pos.y < self.height {
+                        self.labels[self.index(pos)]
+                    } else {
+                        None
+                    }
+                })
+                .collect();
+
+            let mut i = 0;
+            while i < tiles.len() {
+                let other = match labels[i] {
+                    Some(other) => other,
+                    None => {
+                        i += 1;
+                        continue;
+                    }
+                };
+
+                let first = i;
+                while i < tiles.len() && labels[i] == Some(other) {
+                    i += 1;
+                }
+
+                let (from, to) = if vertical {
+                    (tiles[first].y, tiles[i - 1].y + 1)
+                } else {
+                    (tiles[first].x, tiles[i - 1].x + 1)
+                };
+                let (line, from, to) = (f64::from(line), f64::from(from), f64::from(to));
+                let ends = if vertical {
+                    ((line, from), (line, to))
+                } else {
+                    ((from, line), (to, line))
+                };
+
+                portals.push(Portal { region: other, ends });
+            }
+        }
+
+        portals
+    }
+
+    #[inline(always)]
+    fn index(&self, pos: &Position) -> usize {
+        pos.x as usize + pos.y as usize * self.width as usize
+    }
+}
+
+/// Pull a path through a sequence of portals tight, like a string
+///
+/// Each portal is given by its `(left, right)` ends as seen when walking through it.  The
+/// returned path starts at `start`, ends at `goal`, and only turns at the ends of portals.
+/// This is the "simple stupid funnel algorithm": a funnel from the last corner is narrowed
+/// portal by portal, and a new corner is added whenever one side of the funnel crosses over
+/// the other.
+pub fn funnel(start: Point, portals: &[(Point, Point)], goal: Point) -> Vec<Point> {
+    let mut portals = portals.to_vec();
+    portals.push((goal, goal));
+
+    let mut path = vec![start];
+    let (mut apex, mut left, mut right) = (start, start, start);
+    let (mut left_index, mut right_index) = (0, 0);
+
+    let mut i = 0;
+    while i < portals.len() {
+        let (next_left, next_right) = portals[i];
+
+        // Narrow the funnel from the right
+        if area(apex, right, next_right) <= 0.0 {
+            if same(apex, right) || area(apex, left, next_right) > 0.0 {
+                right = next_right;
+                right_index = i + 1;
+            } else {
+                // The right side crossed the left, so the left is a corner of the path
+                path.push(left);
+                apex = left;
+                right = apex;
+                right_index = left_index;
+                i = left_index;
+                continue;
+            }
+        }
+
+        // Narrow the funnel from the left
+        if area(apex, left, next_left) >= 0.0 {
+            if same(apex, left) || area(apex, right, next_left) < 0.0 {
+                left = next_left;
+                left_index = i + 1;
+            } else {
+                path.push(right);
+                apex = right;
+                left = apex;
+                left_index = right_index;
+                i = right_index;
+                continue;
+            }
+        }
+
+        i += 1;
+    }
+
+    if !path.last().map(|&last| same(last, goal)).unwrap_or(false) {
+        path.push(goal);
+    }
+    path
+}
+
+/// Twice the signed area of a triangle, negative when `c` is right of the line from `a` to `b`
+fn area(a: Point, b: Point, c: Point) -> f64 {
+    (c.0 - a.0) * (b.1 - a.1) - (b.0 - a.0) * (c.1 - a.1)
+}
+
+fn cross(a: Point, b: Point) -> f64 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+fn same(a: Point, b: Point) -> bool {
+    (a.0 - b.0).abs() < 1e-9 && (a.1 - b.1).abs() < 1e-9
+}
+
+fn distance(a: Point, b: Point) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+fn center(rect: &Rect) -> Point {
+    (
+        f64::from(rect.pos.x) + f64::from(rect.w) / 2.0,
+        f64::from(rect.pos.y) + f64::from(rect.h) / 2.0,
+    )
+}
+
+/// Entering a region of a navigation mesh at a point
+#[derive(Debug, Clone, PartialEq)]
+pub struct Crossing {
+    pub region: usize,
+    pub point: Point,
+}
+
+impl State for Crossing {
+    type Position = usize;
+
+    fn grid_position(&self) -> usize {
+        self.region
+    }
+
+    fn tile(&self) -> Option<Position> {
+        if self.point.0 < 0.0 || self.point.1 < 0.0 {
+            None
+        } else {
+            Some(Position::new(self.point.0 as u32, self.point.1 as u32))
+        }
+    }
+}
+
+/// Walk from region to region of a navigation mesh through the middle of their portals
+///
+/// The controls are indices into the portals of the current region, and the goal is a point
+/// which is reached upon entering its region.
+#[derive(Debug, Clone)]
+pub struct RegionGraph<'a> {
+    mesh: &'a NavMesh,
+}
+
+impl<'a> RegionGraph<'a> {
+    pub fn new(mesh: &'a NavMesh) -> Self {
+        RegionGraph { mesh }
+    }
+}
+
+impl<'a> Model for RegionGraph<'a> {
+    type State = Crossing;
+    type Control = usize;
+    type Cost = u64;
+    type Goal = Point;
+
+    /// Distance between the points the regions are entered at
+    fn cost(&self, current: &Crossing, _: &usize, next: &Crossing) -> Self::Cost {
+        (distance(current.point, next.point) * COST_SCALE).ceil() as u64
+    }
+
+    /// Nothing to do on initialization
+    #[inline(always)]
+    fn init(&mut self, _: &Crossing) {}
+
+    fn converge(&self, current: &Crossing, goal: &Point) -> bool {
+        self.mesh.region(*goal) == Some(current.region)
+    }
+
+    fn validate(&self, start: &Crossing, goal: &Point) -> Result<(), PathFindingErr> {
+        if self.mesh.region(start.point) != Some(start.region) {
+            return Err(PathFindingErr::StartInvalid(Diagnostics::default()));
+        }
+
+        let (width, height) = (f64::from(self.mesh.width), f64::from(self.mesh.height));
+        if goal.0 < 0.0 || goal.1 < 0.0 || goal.0 >= width || goal.1 >= height {
+            Err(PathFindingErr::GoalOutOfBounds(Diagnostics::default()))
+        } else if self.mesh.region(*goal).is_none() {
+            Err(PathFindingErr::GoalBlocked(Diagnostics::default()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn integrate(&self, previous: &Crossing, portal: &usize) -> Option<Crossing> {
+        self.mesh.portals[previous.region]
+            .get(*portal)
+            .map(|portal| Crossing { region: portal.region, point: portal.midpoint() })
+    }
+}
+
+impl<'a> HeuristicModel for RegionGraph<'a> {
+    /// Straight-line distance to the nearest point of the region of the goal
+    fn heuristic(&self, current: &Crossing, goal: &Point) -> Self::Cost {
+        let rect = match self.mesh.region(*goal) {
+            Some(region) => &self.mesh.regions[region],
+            None => return 0,
+        };
+
+        let (left, top) = (f64::from(rect.pos.x), f64::from(rect.pos.y));
+        let nearest = (
+            current.point.0.max(left).min(left + f64::from(rect.w)),
+            current.point.1.max(top).min(top + f64::from(rect.h)),
+        );
+        (distance(current.point, nearest) * COST_SCALE).floor() as u64
+    }
+}
+
+/// Sample every portal out of the current region
+#[derive(Debug, Clone)]
+pub struct PortalSampler {
+    indices: Vec<usize>,
+}
+
+impl PortalSampler {
+    pub fn new(mesh: &NavMesh) -> Self {
+        let most = mesh.portals.iter().map(Vec::len).max().unwrap_or(0);
+        PortalSampler { indices: (0..most).collect() }
+    }
+}
+
+impl<'a> Sampler<RegionGraph<'a>> for PortalSampler {
+    fn sample(&mut self, model: &RegionGraph<'a>, current: &Crossing) -> &[usize] {
+        &self.indices[..model.mesh.portals[current.region].len()]
+    }
+}
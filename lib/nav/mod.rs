@@ -0,0 +1,231 @@
+//! Navigation structures precomputed over a [`Map`]
+//!
+//! Rather than planning from scratch for every entity, these structures search the map once
+//! and answer many queries from the result.
+//!
+//! They share the [`Grid`] model, which walks between the passable tiles of a map in eight
+//! directions with the same costs as walking with the [`TurnOptimal`] model.
+//!
+//! [`Map`]: ../map/struct.Map.html
+//! [`Grid`]: struct.Grid.html
+//! [`TurnOptimal`]: ../actor/struct.TurnOptimal.html
+
+use crate::actor::Direction;
+use crate::map::{Components, Connectivity, Line, Map};
+use crate::path::{
+    Diagnostics, HeuristicModel, LineOfSight, Model, PathFindingErr, Sampler, Target,
+};
+use crate::{Position, Rect};
+
+pub mod ch;
+pub mod field;
+pub mod first_moves;
+pub mod flow;
+pub mod hpa;
+pub mod landmarks;
+pub mod mesh;
+
+pub use self::ch::ContractionHierarchy;
+pub use self::field::DistanceField;
+pub use self::first_moves::FirstMoves;
+pub use self::flow::FlowField;
+pub use self::hpa::Hierarchy;
+pub use self::landmarks::Landmarks;
+pub use self::mesh::NavMesh;
+
+/// Cost of a step along one axis
+pub const STRAIGHT_COST: usize = 2;
+
+/// Cost of a diagonal step
+pub const DIAGONAL_COST: usize = 3;
+
+/// Walk between the passable tiles of a map
+#[derive(Clone, Debug)]
+pub struct Grid<'a> {
+    map: &'a Map,
+    components: Option<&'a Components>,
+    area: Option<Rect>,
+}
+
+impl<'a> Grid<'a> {
+    pub fn new(map: &'a Map) -> Self {
+        Grid { map, components: None, area: None }
+    }
+
+    /// Reject goals outside of the component of the start before searching
+    ///
+    /// The components must be labeled with 8-connectivity from the same map.
+    pub fn with_components(mut self, components: &'a Components) -> Self {
+        debug_assert_eq!(components.connectivity(), Connectivity::Eight);
+        self.components = Some(components);
+        self
+    }
+
+    /// Only walk inside of an area of `w` by `h` tiles
+    pub fn with_area(mut self, area: Rect) -> Self {
+        self.area = Some(area);
+        self
+    }
+
+    pub fn map(&self) -> &'a Map {
+        self.map
+    }
+
+    /// Test if a position is inside the map and the area, and not blocked
+    pub fn is_passable(&self, pos: &Position) -> bool {
+        let inside = match &self.area {
+            Some(area) => {
+                pos.x >= area.pos.x
+                    && pos.y >= area.pos.y
+                    && pos.x - area.pos.x < area.w
+                    && pos.y - area.pos.y < area.h
+            }
+            None => true,
+        };
+
+        inside && self.map.is_passable(pos)
+    }
+
+    /// The position one step away in a direction, if it is passable
+    pub fn step(&self, pos: &Position, direction: Direction) -> Option<Position> {
+        direction
+            .checked_step(pos.x, pos.y)
+            .map(Position::from)
+            .filter(|next| self.is_passable(next))
+    }
+}
+
+impl<'a> Model for Grid<'a> {
+    type State = Position;
+    type Control = Direction;
+    type Cost = usize;
+    type Goal = Target;
+
+    fn cost(&self, _: &Position, direction: &Direction, _: &Position) -> Self::Cost {
+        step_cost(*direction)
+    }
+
+    /// Nothing to do on initialization
+    #[inline(always)]
+    fn init(&mut self, _: &Position) {}
+
+    fn converge(&self, current: &Position, goal: &Target) -> bool {
+        goal.contains(current)
+    }
+
+    fn validate(&self, start: &Position, goal: &Target) -> Result<(), PathFindingErr> {
+        if !self.is_passable(start) {
+            return Err(PathFindingErr::StartInvalid(Diagnostics::default()));
+        }
+        goal.validate(self.map)?;
+
+        match self.components {
+            Some(components) => goal.validate_connected(components, start),
+            None => Ok(()),
+        }
+    }
+
+    fn integrate(&self, previous: &Position, direction: &Direction) -> Option<Position> {
+        self.step(previous, *direction)
+    }
+}
+
+impl<'a> HeuristicModel for Grid<'a> {
+    /// The octile distance, which is exact on an open map
+    fn heuristic(&self, current: &Position, goal: &Target) -> Self::Cost {
+        goal.estimate(current, octile).unwrap_or(0)
+    }
+}
+
+impl<'a> LineOfSight for Grid<'a> {
+    fn line_of_sight(&self, from: &Position, to: &Position) -> bool {
+        Line::new(from, to).all(|pos| self.is_passable(&pos))
+    }
+
+    /// The octile distance, which is the cost of walking the line one step at a time
+    fn distance(&self, from: &Position, to: &Position) -> Self::Cost {
+        octile(from, to)
+    }
+}
+
+/// Sample a step in every direction
+#[derive(Debug, Clone, Default)]
+pub struct Octile;
+
+impl<'a> Sampler<Grid<'a>> for Octile {
+    #[inline(always)]
+    fn sample(&mut self, _: &Grid<'a>, _: &Position) -> &[Direction] {
+        &Direction::ALL
+    }
+}
+
+/// Cost of a single step in a direction
+pub fn step_cost(direction: Direction) -> usize {
+    if direction.is_diagonal() {
+        DIAGONAL_COST
+    } else {
+        STRAIGHT_COST
+    }
+}
+
+/// Cost of the cheapest path between two positions on an open map
+pub fn octile(a: &Position, b: &Position) -> usize {
+    let dx = (i64::from(a.x) - i64::from(b.x)).unsigned_abs() as usize;
+    let dy = (i64::from(a.y) - i64::from(b.y)).unsigned_abs() as usize;
+
+    STRAIGHT_COST * dx.max(dy) + (DIAGONAL_COST - STRAIGHT_COST) * dx.min(dy)
+}
+
+/// The steps to walk along the straight lines between consecutive waypoints
+///
+/// Any-angle paths, such as the ones of [`ThetaStar`] on a [`Grid`], only keep the turning
+/// points of the path.  Walking the [`Line`] between each pair of waypoints visits the same
+/// tiles as the line of sight test which linked them.
+///
+/// ```
+/// # use game_lib::map::{Map, Tile};
+/// # use game_lib::Position;
+/// use game_lib::nav::{walk, Grid, Octile};
+/// use game_lib::path::theta::ThetaStar;
+/// use game_lib::path::{Optimizer, PathResult, Target};
+///
+/// let mut map = Map::new(12, 8);
+/// for y in 1..7 {
+///     for x in 1..11 {
+///         map[(x, y)] = Tile::FLOOR;
+///     }
+/// }
+///
+/// let start = Position::new(1, 1);
+/// let goal = Target::At(Position::new(10, 4));
+/// let result = ThetaStar::new().optimize(&mut Grid::new(&map), &start, &goal, &mut Octile);
+/// let waypoints: Vec<Position> = match result {
+///     PathResult::Final(trajectory) => trajectory.trajectory.into_iter().map(|s| s.0).collect(),
+///     _ => unreachable!(),
+/// };
+/// // A straight line across the room
+/// assert_eq!(waypoints, vec![start.clone(), Position::new(10, 4)]);
+///
+/// let steps = walk(&waypoints);
+/// assert_eq!(steps.len(), 9);
+/// let end = steps.iter().fold(start, |pos, direction| {
+///     direction.checked_step(pos.x, pos.y).map(Position::from).unwrap()
+/// });
+/// assert_eq!(end, Position::new(10, 4));
+/// ```
+///
+/// [`ThetaStar`]: ../path/theta/struct.ThetaStar.html
+/// [`Grid`]: struct.Grid.html
+/// [`Line`]: ../map/struct.Line.html
+pub fn walk(waypoints: &[Position]) -> Vec<Direction> {
+    waypoints
+        .windows(2)
+        .flat_map(|pair| {
+            let tiles: Vec<Position> = Line::new(&pair[0], &pair[1]).collect();
+            tiles
+                .windows(2)
+                .filter_map(|step| Direction::between(&step[0], &step[1]))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
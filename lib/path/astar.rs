@@ -5,8 +5,9 @@ use radix_heap::RadixHeapMap;
 use std::cmp::{Ord, Ordering, PartialEq, PartialOrd, Reverse};
 use std::collections::hash_map::Entry;
 use std::collections::BinaryHeap;
-use std::hash::{Hash, Hasher};
 
+use super::budget::Progress;
+use super::dominance::Labels;
 use super::*;
 
 pub struct OptimalAStar<M>
@@ -15,9 +16,12 @@ where
     M::Cost: radix_heap::Radix + Copy,
 {
     queue: RadixHeapMap<Reverse<M::Cost>, Node<M>>,
-    parent_map: FnvHashMap<Id<M>, Node<M>>,
+    parent_map: FnvHashMap<usize, Node<M>>,
     grid: FnvHashMap<<<M as Model>::State as State>::Position, Id<M>>,
     id_counter: usize,
+    labels: Labels<M>,
+    budget: Budget<M>,
+    searching: bool,
 }
 
 impl<M> OptimalAStar<M>
@@ -32,13 +36,92 @@ where
             parent_map: FnvHashMap::default(),
             grid: FnvHashMap::default(),
             id_counter: 0,
+            labels: Labels::new(),
+            budget: Budget::unlimited(),
+            searching: false,
         }
     }
 
+    /// Create a new optimizer with room to discover `states` states without allocating
+    ///
+    /// The radix heap cannot reserve memory up front, so its buckets still grow during the
+    /// first searches.
+    pub fn with_capacity(states: usize) -> Self {
+        OptimalAStar {
+            queue: RadixHeapMap::new(),
+            parent_map: FnvHashMap::with_capacity_and_hasher(states, Default::default()),
+            grid: FnvHashMap::with_capacity_and_hasher(states, Default::default()),
+            id_counter: 0,
+            labels: Labels::new(),
+            budget: Budget::unlimited(),
+            searching: false,
+        }
+    }
+
+    /// Forget the previous search, keeping the memory it used for the following searches
     pub fn clear(&mut self) {
         self.queue.clear();
         self.parent_map.clear();
         self.grid.clear();
+        self.labels.clear();
+        self.id_counter = 0;
+        self.searching = false;
+    }
+
+    /// Keep every state which no other state at its grid position dominates
+    ///
+    /// See [`Dominance`].  A state which was costlier to reach is kept when it has something
+    /// the cheaper one lacks, here the mana to teleport:
+    ///
+    /// ```
+    /// # use game_lib::map::{Map, Tile};
+    /// # use game_lib::Position;
+    /// use game_lib::actor::{Actor, Heuristic, MoveSet, TeleportSampler, TurnOptimal};
+    /// use game_lib::path::{astar::AStar, Optimizer, PathResult, Target};
+    ///
+    /// let mut map = Map::new(16, 4);
+    /// for x in 1..15 {
+    ///     map[(x, 1)] = Tile::FLOOR;
+    /// }
+    /// map[(1, 2)] = Tile::FLOOR;
+    /// map[(2, 2)] = Tile::FLOOR;
+    ///
+    /// let mut model = TurnOptimal::new(map);
+    /// model.set_heuristic(Heuristic::Moves(MoveSet::teleport()));
+    ///
+    /// // Stepping diagonally onto (2, 1) is cheaper, but leaves too little mana to teleport
+    /// // from there to the goal, which the two straight steps would have regenerated
+    /// let start = Actor::new(1, 2, 3, 10);
+    /// let goal = Target::At(Position::new(12, 1));
+    /// let cost = |planner: &mut AStar<TurnOptimal>, model: &mut TurnOptimal| {
+    ///     match planner.optimize(model, &start, &goal, &mut TeleportSampler::new()) {
+    ///         PathResult::Final(trajectory) => trajectory.cost,
+    ///         _ => panic!("the corridor is open"),
+    ///     }
+    /// };
+    ///
+    /// assert_eq!(cost(&mut AStar::new(), &mut model), 9);
+    ///
+    /// let mut planner = AStar::new();
+    /// planner.use_dominance();
+    /// assert_eq!(cost(&mut planner, &mut model), 6);
+    /// ```
+    ///
+    /// [`Dominance`]: ../trait.Dominance.html
+    pub fn use_dominance(&mut self)
+    where
+        M::State: Dominance,
+    {
+        self.labels.enable(<M::State as Dominance>::dominates)
+    }
+
+    pub fn budget(&self) -> &Budget<M> {
+        &self.budget
+    }
+
+    /// Limit the work of the following searches, and what they return when giving up
+    pub fn set_budget(&mut self, budget: Budget<M>) {
+        self.budget = budget
     }
 
     pub fn inspect_queue(&self) -> impl Iterator<Item = (&M::State, &M::Control)> {
@@ -56,22 +139,25 @@ where
         &mut self,
         current: &Node<M>,
         model: &mut M,
-        goal: &M::State,
+        goals: &[M::Goal],
         sampler: &mut S,
-    ) -> bool
+    ) -> Option<usize>
     where
         S: Sampler<M>,
     {
-        if model.converge(&current.state, goal) {
-            return true;
+        if let Some(reached) =
+            goals.iter().position(|goal| model.converge(&current.state, goal))
+        {
+            return Some(reached);
         }
 
+        model.on_expand(&current.state);
         for control in sampler.sample(model, &current.state) {
             if let Some(child_state) = model.integrate(&current.state, &control) {
                 self.id_counter += 1;
 
                 let cost = current.id.g() + model.cost(&current.state, &control, &child_state);
-                let heuristic = model.heuristic(&child_state, goal);
+                let heuristic = estimate(model, &child_state, goals);
 
                 let child = Node::<M> {
                     id: Id::new(self.id_counter, cost + heuristic, cost),
@@ -79,13 +165,21 @@ where
                     control: control.clone(),
                 };
 
+                // Compared by dominance, a costlier state is kept unless a label dominates it
+                let dominant = self.labels.admit(&child.state, cost, child.id.id);
+                if dominant == Some(false) {
+                    continue;
+                }
+
                 let position = self.grid.entry(child.state.grid_position());
 
                 match position {
                     Entry::Occupied(mut best) => {
                         let best = best.get_mut();
                         if best.g <= child.id.g {
-                            continue;
+                            if dominant.is_none() {
+                                continue;
+                            }
                         } else {
                             *best = child.id.clone();
                         }
@@ -95,22 +189,38 @@ where
                     }
                 }
 
-                self.parent_map.insert(child.id.clone(), current.clone());
+                self.parent_map.insert(child.id.id, current.clone());
                 self.queue.push(child.id.f, child);
             }
         }
 
-        false
+        None
+    }
+
+    /// Take the next node off the queue, dropping the nodes pruned by dominance
+    fn pop(&mut self) -> Option<Node<M>> {
+        while let Some((_, node)) = self.queue.pop() {
+            if !self.labels.take_pruned(node.id.id) {
+                return Some(node);
+            }
+            self.parent_map.remove(&node.id.id);
+        }
+        None
     }
 
     /// Follow the parents from the goal node up to the start node
-    fn unwind_trajectory(&self, model: &M, mut current: Node<M>) -> Trajectory<M> {
-        let mut result = Vec::new();
+    fn unwind_trajectory(
+        &self,
+        model: &M,
+        mut current: Node<M>,
+        goal: usize,
+    ) -> Trajectory<M> {
+        let mut result = Vec::with_capacity(self.depth(&current));
         result.push((current.state.clone(), current.control.clone()));
         let mut cost = M::Cost::default();
 
         // build up the trajectory by following the parent nodes
-        while let Some(p) = self.parent_map.get(&current.id) {
+        while let Some(p) = self.parent_map.get(&current.id.id) {
             cost = cost + model.cost(&current.state, &current.control, &p.state);
             current = (*p).clone();
             result.push((current.state.clone(), current.control.clone()));
@@ -118,7 +228,18 @@ where
 
         result.reverse();
 
-        Trajectory { cost, trajectory: result }
+        Trajectory { cost, trajectory: result, goal }
+    }
+
+    /// The number of nodes from a node up to the start node
+    fn depth(&self, node: &Node<M>) -> usize {
+        let mut depth = 1;
+        let mut current = node.id.id;
+        while let Some(parent) = self.parent_map.get(&current) {
+            current = parent.id.id;
+            depth += 1;
+        }
+        depth
     }
 }
 
@@ -128,68 +249,106 @@ where
     M::Cost: radix_heap::Radix + Copy,
     S: Sampler<M>,
 {
+    fn clear(&mut self) {
+        OptimalAStar::clear(self)
+    }
+
     fn next_trajectory(
         &mut self,
         model: &mut M,
         start: &M::State,
-        goal: &M::State,
+        goal: &M::Goal,
         sampler: &mut S,
     ) -> PathResult<M> {
         use PathFindingErr::*;
         use PathResult::*;
 
-        if self.parent_map.is_empty() && self.queue.is_empty() {
+        if !self.searching {
+            self.clear();
+            if let Some(err) =
+                validate(model, start, std::slice::from_ref(goal), &self.budget).err()
+            {
+                return Err(err);
+            }
+
+            model.init(start);
             let heuristic = model.heuristic(start, goal);
             let start_id = Id::new(0, heuristic, Default::default());
             self.queue.push(
                 Default::default(),
                 Node { id: start_id, state: start.clone(), control: Default::default() },
             );
+            self.searching = true;
         }
 
-        if let Some((_, current)) = self.queue.pop() {
-            if self.step(&current, model, &goal, sampler) {
-                Final(self.unwind_trajectory(model, current))
-            } else {
-                Intermediate(self.unwind_trajectory(model, current))
+        let result = if let Some(current) = self.pop() {
+            match self.step(&current, model, std::slice::from_ref(goal), sampler) {
+                Some(reached) => Final(self.unwind_trajectory(model, current, reached)),
+                None => Intermediate(self.unwind_trajectory(model, current, 0)),
             }
         } else {
-            Err(Unreachable)
-        }
+            Err(Unreachable(Diagnostics { closed: self.grid.len(), ..Default::default() }))
+        };
+
+        conclude(model, result, &mut self.searching)
     }
 
-    fn optimize(
+    fn optimize_nearest(
         &mut self,
         model: &mut M,
         start: &M::State,
-        goal: &M::State,
+        goals: &[M::Goal],
         sampler: &mut S,
     ) -> PathResult<M> {
-        use PathFindingErr::*;
         use PathResult::*;
 
-        if model.converge(start, goal) {
-            return Final(Trajectory {
-                cost: Default::default(),
-                trajectory: vec![(start.clone(), Default::default())],
-            });
-        }
+        if !self.searching {
+            self.clear();
+            if let Some(reached) = goals.iter().position(|goal| model.converge(start, goal)) {
+                model.init(start);
+                return conclude(
+                    model,
+                    Final(Trajectory {
+                        cost: Default::default(),
+                        trajectory: vec![(start.clone(), Default::default())],
+                        goal: reached,
+                    }),
+                    &mut self.searching,
+                );
+            }
+
+            if let Some(err) = validate(model, start, goals, &self.budget).err() {
+                return Err(err);
+            }
 
-        if self.queue.top().is_none() {
-            let start_id = Id::new(0, model.heuristic(start, goal), Default::default());
+            model.init(start);
+            let start_id = Id::new(0, estimate(model, start, goals), Default::default());
             self.queue.push(
                 Default::default(),
                 Node { id: start_id, state: start.clone(), control: Default::default() },
             );
+            self.searching = true;
         }
 
-        while let Some((_, current)) = self.queue.pop() {
-            if self.step(&current, model, &goal, sampler) {
-                return Final(self.unwind_trajectory(model, current));
+        let mut progress = Progress::new();
+
+        while let Some(current) = self.pop() {
+            if let Some(reached) = self.step(&current, model, goals, sampler) {
+                let result = Final(self.unwind_trajectory(model, current, reached));
+                return conclude(model, result, &mut self.searching);
+            }
+
+            if !progress.expand(&self.budget, model, &current.state, goals, || current.clone())
+            {
+                break;
             }
         }
 
-        Err(Unreachable)
+        let result = progress.finish(self.grid.len()).map_or_else(Err, |(node, goal)| {
+            Partial(self.unwind_trajectory(model, node, goal))
+        });
+
+        conclude(model, result, &mut self.searching)
     }
 }
 
@@ -227,9 +386,12 @@ where
     M::Cost: Copy,
 {
     queue: BinaryHeap<Node<M>>,
-    parent_map: FnvHashMap<Id<M>, Node<M>>,
+    parent_map: FnvHashMap<usize, Node<M>>,
     grid: FnvHashMap<<<M as Model>::State as State>::Position, Id<M>>,
     id_counter: usize,
+    labels: Labels<M>,
+    budget: Budget<M>,
+    searching: bool,
 }
 
 impl<M> AStar<M>
@@ -244,13 +406,54 @@ where
             parent_map: FnvHashMap::default(),
             grid: FnvHashMap::default(),
             id_counter: 0,
+            labels: Labels::new(),
+            budget: Budget::unlimited(),
+            searching: false,
         }
     }
 
+    /// Create a new optimizer with room to discover `states` states without allocating
+    pub fn with_capacity(states: usize) -> Self {
+        AStar {
+            queue: BinaryHeap::with_capacity(states),
+            parent_map: FnvHashMap::with_capacity_and_hasher(states, Default::default()),
+            grid: FnvHashMap::with_capacity_and_hasher(states, Default::default()),
+            id_counter: 0,
+            labels: Labels::new(),
+            budget: Budget::unlimited(),
+            searching: false,
+        }
+    }
+
+    /// Forget the previous search, keeping the memory it used for the following searches
     pub fn clear(&mut self) {
         self.queue.clear();
         self.parent_map.clear();
         self.grid.clear();
+        self.labels.clear();
+        self.id_counter = 0;
+        self.searching = false;
+    }
+
+    /// Keep every state which no other state at its grid position dominates
+    ///
+    /// See [`Dominance`].
+    ///
+    /// [`Dominance`]: ../trait.Dominance.html
+    pub fn use_dominance(&mut self)
+    where
+        M::State: Dominance,
+    {
+        self.labels.enable(<M::State as Dominance>::dominates)
+    }
+
+    pub fn budget(&self) -> &Budget<M> {
+        &self.budget
+    }
+
+    /// Limit the work of the following searches, and what they return when giving up
+    pub fn set_budget(&mut self, budget: Budget<M>) {
+        self.budget = budget
     }
 
     pub fn inspect_queue(&self) -> impl Iterator<Item = (&M::State, &M::Control)> {
@@ -268,22 +471,25 @@ where
         &mut self,
         current: &Node<M>,
         model: &mut M,
-        goal: &M::State,
+        goals: &[M::Goal],
         sampler: &mut S,
-    ) -> bool
+    ) -> Option<usize>
     where
         S: Sampler<M>,
     {
-        if model.converge(&current.state, goal) {
-            return true;
+        if let Some(reached) =
+            goals.iter().position(|goal| model.converge(&current.state, goal))
+        {
+            return Some(reached);
         }
 
+        model.on_expand(&current.state);
         for control in sampler.sample(model, &current.state) {
             if let Some(child_state) = model.integrate(&current.state, &control) {
                 self.id_counter += 1;
 
                 let cost = current.id.g() + model.cost(&current.state, &control, &child_state);
-                let heuristic = model.heuristic(&child_state, goal);
+                let heuristic = estimate(model, &child_state, goals);
 
                 let child = Node::<M> {
                     id: Id::new(self.id_counter, cost + heuristic, cost),
@@ -291,13 +497,21 @@ where
                     control: control.clone(),
                 };
 
+                // Compared by dominance, a costlier state is kept unless a label dominates it
+                let dominant = self.labels.admit(&child.state, cost, child.id.id);
+                if dominant == Some(false) {
+                    continue;
+                }
+
                 let position = self.grid.entry(child.state.grid_position());
 
                 match position {
                     Entry::Occupied(mut best) => {
                         let best = best.get_mut();
                         if best.g <= child.id.g {
-                            continue;
+                            if dominant.is_none() {
+                                continue;
+                            }
                         } else {
                             *best = child.id.clone();
                         }
@@ -307,22 +521,38 @@ where
                     }
                 }
 
-                self.parent_map.insert(child.id.clone(), current.clone());
+                self.parent_map.insert(child.id.id, current.clone());
                 self.queue.push(child);
             }
         }
 
-        false
+        None
+    }
+
+    /// Take the next node off the queue, dropping the nodes pruned by dominance
+    fn pop(&mut self) -> Option<Node<M>> {
+        while let Some(node) = self.queue.pop() {
+            if !self.labels.take_pruned(node.id.id) {
+                return Some(node);
+            }
+            self.parent_map.remove(&node.id.id);
+        }
+        None
     }
 
     /// Follow the parents from the goal node up to the start node
-    fn unwind_trajectory(&self, model: &M, mut current: Node<M>) -> Trajectory<M> {
-        let mut result = Vec::new();
+    fn unwind_trajectory(
+        &self,
+        model: &M,
+        mut current: Node<M>,
+        goal: usize,
+    ) -> Trajectory<M> {
+        let mut result = Vec::with_capacity(self.depth(&current));
         result.push((current.state.clone(), current.control.clone()));
         let mut cost = M::Cost::default();
 
         // build up the trajectory by following the parent nodes
-        while let Some(p) = self.parent_map.get(&current.id) {
+        while let Some(p) = self.parent_map.get(&current.id.id) {
             cost = cost + model.cost(&current.state, &current.control, &p.state);
             current = (*p).clone();
             result.push((current.state.clone(), current.control.clone()));
@@ -330,7 +560,18 @@ where
 
         result.reverse();
 
-        Trajectory { cost, trajectory: result }
+        Trajectory { cost, trajectory: result, goal }
+    }
+
+    /// The number of nodes from a node up to the start node
+    fn depth(&self, node: &Node<M>) -> usize {
+        let mut depth = 1;
+        let mut current = node.id.id;
+        while let Some(parent) = self.parent_map.get(&current) {
+            current = parent.id.id;
+            depth += 1;
+        }
+        depth
     }
 }
 
@@ -340,17 +581,29 @@ where
     M::Cost: radix_heap::Radix + Copy,
     S: Sampler<M>,
 {
+    fn clear(&mut self) {
+        AStar::clear(self)
+    }
+
     fn next_trajectory(
         &mut self,
         model: &mut M,
         start: &M::State,
-        goal: &M::State,
+        goal: &M::Goal,
         sampler: &mut S,
     ) -> PathResult<M> {
         use PathFindingErr::*;
         use PathResult::*;
 
-        if self.parent_map.is_empty() && self.queue.is_empty() {
+        if !self.searching {
+            self.clear();
+            if let Some(err) =
+                validate(model, start, std::slice::from_ref(goal), &self.budget).err()
+            {
+                return Err(err);
+            }
+
+            model.init(start);
             let heuristic = model.heuristic(start, goal);
             let start_id = Id::new(0, heuristic, Default::default());
             self.queue.push(Node {
@@ -358,52 +611,78 @@ where
                 state: start.clone(),
                 control: Default::default(),
             });
+            self.searching = true;
         }
 
-        if let Some(current) = self.queue.pop() {
-            if self.step(&current, model, &goal, sampler) {
-                Final(self.unwind_trajectory(model, current))
-            } else {
-                Intermediate(self.unwind_trajectory(model, current))
+        let result = if let Some(current) = self.pop() {
+            match self.step(&current, model, std::slice::from_ref(goal), sampler) {
+                Some(reached) => Final(self.unwind_trajectory(model, current, reached)),
+                None => Intermediate(self.unwind_trajectory(model, current, 0)),
             }
         } else {
-            Err(Unreachable)
-        }
+            Err(Unreachable(Diagnostics { closed: self.grid.len(), ..Default::default() }))
+        };
+
+        conclude(model, result, &mut self.searching)
     }
 
-    fn optimize(
+    fn optimize_nearest(
         &mut self,
         model: &mut M,
         start: &M::State,
-        goal: &M::State,
+        goals: &[M::Goal],
         sampler: &mut S,
     ) -> PathResult<M> {
-        use PathFindingErr::*;
         use PathResult::*;
 
-        if model.converge(start, goal) {
-            return Final(Trajectory {
-                cost: Default::default(),
-                trajectory: vec![(start.clone(), Default::default())],
-            });
-        }
+        if !self.searching {
+            self.clear();
+            if let Some(reached) = goals.iter().position(|goal| model.converge(start, goal)) {
+                model.init(start);
+                return conclude(
+                    model,
+                    Final(Trajectory {
+                        cost: Default::default(),
+                        trajectory: vec![(start.clone(), Default::default())],
+                        goal: reached,
+                    }),
+                    &mut self.searching,
+                );
+            }
 
-        if self.queue.pop().is_none() {
-            let start_id = Id::new(0, model.heuristic(start, goal), Default::default());
+            if let Some(err) = validate(model, start, goals, &self.budget).err() {
+                return Err(err);
+            }
+
+            model.init(start);
+            let start_id = Id::new(0, estimate(model, start, goals), Default::default());
             self.queue.push(Node {
                 id: start_id,
                 state: start.clone(),
                 control: Default::default(),
             });
+            self.searching = true;
         }
 
-        while let Some(current) = self.queue.pop() {
-            if self.step(&current, model, &goal, sampler) {
-                return Final(self.unwind_trajectory(model, current));
+        let mut progress = Progress::new();
+
+        while let Some(current) = self.pop() {
+            if let Some(reached) = self.step(&current, model, goals, sampler) {
+                let result = Final(self.unwind_trajectory(model, current, reached));
+                return conclude(model, result, &mut self.searching);
+            }
+
+            if !progress.expand(&self.budget, model, &current.state, goals, || current.clone())
+            {
+                break;
             }
         }
 
-        Err(Unreachable)
+        let result = progress.finish(self.grid.len()).map_or_else(Err, |(node, goal)| {
+            Partial(self.unwind_trajectory(model, node, goal))
+        });
+
+        conclude(model, result, &mut self.searching)
     }
 }
 
@@ -435,12 +714,24 @@ where
     }
 }
 
+/// The most optimistic estimate of the cost to any of the goals
+#[inline(always)]
+pub(super) fn estimate<M>(model: &M, state: &M::State, goals: &[M::Goal]) -> M::Cost
+where
+    M: HeuristicModel,
+{
+    goals.iter().map(|goal| model.heuristic(state, goal)).min().unwrap_or_default()
+}
+
 /// The Id which identifies a particular node and allows for comparisons
+///
+/// Ids compare by their estimated cost, to order the queue, so nodes are looked up by the
+/// integer `id` alone.
 struct Id<M>
 where
     M: Model,
 {
-    /// Simple integer ID which must be unique
+    /// Simple integer ID which must be unique, and keys the parent map
     id: usize,
     /// Estimated cost including the heuristic
     f: Reverse<M::Cost>,
@@ -471,15 +762,6 @@ where
     }
 }
 
-impl<M> Hash for Id<M>
-where
-    M: Model,
-{
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.id.hash(state);
-    }
-}
-
 impl<M> PartialEq for Id<M>
 where
     M: Model,
@@ -0,0 +1,184 @@
+//! Planning for many entities at once, across worker threads
+//!
+//! A [`BatchPlanner`] keeps a pool of threads, each with its own optimizer and sampler which
+//! are reused from one query to the next.  Every batch shares one model, and each worker
+//! plans with a copy of it, so models which are cheap to clone, such as a `TurnOptimal`
+//! sharing its map through an `Arc`, are best.
+//!
+//! ```
+//! # use game_lib::map::{Map, Tile};
+//! # use game_lib::Position;
+//! use game_lib::actor::{Actor, Heuristic, TurnOptimal, WalkSampler};
+//! use game_lib::path::astar::AStar;
+//! use game_lib::path::batch::BatchPlanner;
+//! use game_lib::path::{PathResult, Target};
+//!
+//! let mut map = Map::new(20, 3);
+//! for x in 1..19 {
+//!     map[(x, 1)] = Tile::FLOOR;
+//! }
+//!
+//! let mut model = TurnOptimal::new(map);
+//! model.set_heuristic(Heuristic::Diagonal);
+//!
+//! let planner = BatchPlanner::new(4, AStar::new, WalkSampler::new);
+//! let queries = (1..19)
+//!     .map(|x| (Actor::new(x, 1, 0, 0), Target::At(Position::new(10, 1))))
+//!     .collect();
+//!
+//! let results = planner.plan(&model, queries);
+//! assert_eq!(results.len(), 18);
+//! match &results[0] {
+//!     PathResult::Final(trajectory) => assert_eq!(trajectory.cost, 2 * 9),
+//!     _ => panic!("the corridor is open"),
+//! }
+//! ```
+//!
+//! [`BatchPlanner`]: struct.BatchPlanner.html
+
+use super::{Model, Optimizer, PathResult, Sampler};
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+
+/// A batch of queries, handed to every worker which then takes queries until none are left
+struct Job<M>
+where
+    M: Model,
+{
+    model: Arc<M>,
+    queries: Arc<Vec<(M::State, M::Goal)>>,
+    next: Arc<AtomicUsize>,
+    results: Sender<(usize, PathResult<M>)>,
+}
+
+/// A pool of worker threads which solve batches of queries against a shared model
+pub struct BatchPlanner<M>
+where
+    M: Model,
+{
+    jobs: Option<Sender<Job<M>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl<M> BatchPlanner<M>
+where
+    M: Model + Clone + Send + Sync + 'static,
+    M::State: Send + Sync,
+    M::Control: Send,
+    M::Cost: Send,
+    M::Goal: Send + Sync,
+{
+    /// Start `threads` workers, each creating its own optimizer and sampler
+    pub fn new<O, S, F, G>(threads: usize, optimizer: F, sampler: G) -> Self
+    where
+        O: Optimizer<M, S>,
+        S: Sampler<M>,
+        F: Fn() -> O + Send + Sync + 'static,
+        G: Fn() -> S + Send + Sync + 'static,
+    {
+        let (jobs, receiver) = mpsc::channel::<Job<M>>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let (optimizer, sampler) = (Arc::new(optimizer), Arc::new(sampler));
+
+        let workers = (0..threads.max(1))
+            .map(|_| {
+                let (receiver, optimizer, sampler) =
+                    (Arc::clone(&receiver), Arc::clone(&optimizer), Arc::clone(&sampler));
+                thread::spawn(move || work(&receiver, optimizer(), sampler()))
+            })
+            .collect();
+
+        BatchPlanner { jobs: Some(jobs), workers }
+    }
+
+    /// The number of worker threads
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Solve every query with a copy of the model, returning the results in query order
+    ///
+    /// # Panics
+    ///
+    /// Panics when a worker panicked while planning.
+    pub fn plan(&self, model: &M, queries: Vec<(M::State, M::Goal)>) -> Vec<PathResult<M>> {
+        let count = queries.len();
+        let (results, received) = mpsc::channel();
+        let job = Job {
+            model: Arc::new(model.clone()),
+            queries: Arc::new(queries),
+            next: Arc::new(AtomicUsize::new(0)),
+            results,
+        };
+
+        let jobs = self.jobs.as_ref().expect("the workers run until the planner is dropped");
+        for _ in 0..self.workers.len() {
+            let job = Job {
+                model: Arc::clone(&job.model),
+                queries: Arc::clone(&job.queries),
+                next: Arc::clone(&job.next),
+                results: job.results.clone(),
+            };
+            jobs.send(job).expect("a planning worker panicked");
+        }
+        // Receiving ends once every worker is done with the batch
+        drop(job);
+
+        let mut ordered: Vec<Option<PathResult<M>>> = (0..count).map(|_| None).collect();
+        for (index, result) in received {
+            ordered[index] = Some(result);
+        }
+
+        ordered.into_iter().map(|result| result.expect("a planning worker panicked")).collect()
+    }
+}
+
+impl<M> Drop for BatchPlanner<M>
+where
+    M: Model,
+{
+    fn drop(&mut self) {
+        // Closing the channel stops the workers once they finish their current batch
+        self.jobs = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Solve the queries of every job received until the planner is dropped
+fn work<M, O, S>(jobs: &Mutex<Receiver<Job<M>>>, mut optimizer: O, mut sampler: S)
+where
+    M: Model + Clone,
+    O: Optimizer<M, S>,
+    S: Sampler<M>,
+{
+    loop {
+        let job = match jobs.lock() {
+            Ok(jobs) => jobs.recv(),
+            Err(_) => return,
+        };
+        let job = match job {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+
+        let mut model = (*job.model).clone();
+        loop {
+            let index = job.next.fetch_add(1, Ordering::Relaxed);
+            let (start, goal) = match job.queries.get(index) {
+                Some(query) => query,
+                None => break,
+            };
+
+            optimizer.clear();
+            let result = optimizer.optimize(&mut model, start, goal, &mut sampler);
+            if job.results.send((index, result)).is_err() {
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,368 @@
+use std::fmt::{Debug, Formatter};
+
+use fnv::FnvHashMap;
+use std::collections::hash_map::Entry;
+
+use super::astar::estimate;
+use super::budget::{Budget, Progress};
+use super::*;
+
+/// Beam search, which only keeps the most promising states of every layer
+///
+/// The search expands the states one layer at a time, where a layer holds the states reached
+/// with the same number of controls.  Only the `width` states of each layer with the lowest
+/// estimated total cost are expanded further, so memory and time grow linearly with the
+/// length of the path instead of with the size of the search space.
+///
+/// The price is completeness: the states leading to the goal may be dropped from the beam,
+/// in which case the beam empties and the search reports the goal as unreachable.  The
+/// trajectory found is not necessarily the cheapest either.
+///
+/// ```
+/// # use game_lib::map::{Map, Tile};
+/// # use game_lib::Position;
+/// use game_lib::nav::{Grid, Octile};
+/// use game_lib::path::beam::Beam;
+/// use game_lib::path::{Optimizer, PathFindingErr, PathResult, Target};
+///
+/// // The corridor straight to the goal is a dead end, the way around starts further away
+/// let mut map = Map::new(12, 7);
+/// for x in 1..9 {
+///     map[(x, 3)] = Tile::FLOOR;
+/// }
+/// for x in 1..11 {
+///     map[(x, 5)] = Tile::FLOOR;
+/// }
+/// map[(1, 4)] = Tile::FLOOR;
+/// map[(10, 4)] = Tile::FLOOR;
+/// map[(10, 3)] = Tile::FLOOR;
+///
+/// let start = Position::new(1, 3);
+/// let goal = Target::At(Position::new(10, 3));
+///
+/// // A single state per layer follows the dead end, and the way around is evicted
+/// match Beam::new(1).optimize(&mut Grid::new(&map), &start, &goal, &mut Octile) {
+///     PathResult::Err(PathFindingErr::Unreachable(_)) => {}
+///     _ => panic!("the beam is too narrow"),
+/// }
+///
+/// match Beam::new(2).optimize(&mut Grid::new(&map), &start, &goal, &mut Octile) {
+///     PathResult::Final(trajectory) => {
+///         assert_eq!(trajectory.trajectory.last().unwrap().0, Position::new(10, 3))
+///     }
+///     _ => panic!("the beam keeps the way around"),
+/// }
+/// ```
+pub struct Beam<M>
+where
+    M: HeuristicModel,
+    M::Cost: Copy,
+{
+    width: usize,
+    layer: Vec<usize>,
+    nodes: Vec<Node<M>>,
+    grid: FnvHashMap<<<M as Model>::State as State>::Position, M::Cost>,
+    budget: Budget<M>,
+    searching: bool,
+}
+
+struct Node<M>
+where
+    M: Model,
+{
+    state: M::State,
+    control: M::Control,
+    g: M::Cost,
+    f: M::Cost,
+    parent: Option<usize>,
+}
+
+impl<M> Beam<M>
+where
+    M: HeuristicModel,
+    M::Cost: Copy,
+{
+    /// Create a beam search which keeps `width` states per layer
+    pub fn new(width: usize) -> Self {
+        Beam {
+            width: width.max(1),
+            layer: Vec::new(),
+            nodes: Vec::new(),
+            grid: FnvHashMap::default(),
+            budget: Budget::unlimited(),
+            searching: false,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn clear(&mut self) {
+        self.layer.clear();
+        self.nodes.clear();
+        self.grid.clear();
+        self.searching = false;
+    }
+
+    pub fn budget(&self) -> &Budget<M> {
+        &self.budget
+    }
+
+    /// Limit the work of the following searches, and what they return when giving up
+    pub fn set_budget(&mut self, budget: Budget<M>) {
+        self.budget = budget
+    }
+
+    pub fn inspect_queue(&self) -> impl Iterator<Item = (&M::State, &M::Control)> {
+        self.layer.iter().map(move |&i| (&self.nodes[i].state, &self.nodes[i].control))
+    }
+
+    pub fn inspect_discovered(
+        &self,
+    ) -> impl Iterator<Item = &<<M as Model>::State as State>::Position> {
+        self.grid.keys()
+    }
+
+    fn start(&mut self, model: &M, start: &M::State, goals: &[M::Goal]) {
+        let g = M::Cost::default();
+        self.grid.insert(start.grid_position(), g);
+        self.layer.push(self.nodes.len());
+        self.nodes.push(Node {
+            state: start.clone(),
+            control: Default::default(),
+            g,
+            f: g + estimate(model, start, goals),
+            parent: None,
+        });
+    }
+
+    /// Check the current layer for a goal, otherwise replace it with the next layer
+    ///
+    /// Returns the node and index of the cheapest goal reached in the current layer.
+    fn step<S>(
+        &mut self,
+        model: &mut M,
+        goals: &[M::Goal],
+        sampler: &mut S,
+    ) -> Option<(usize, usize)>
+    where
+        S: Sampler<M>,
+    {
+        let reached = self
+            .layer
+            .iter()
+            .filter_map(|&i| {
+                let state = &self.nodes[i].state;
+                goals.iter().position(|goal| model.converge(state, goal)).map(|goal| (i, goal))
+            })
+            .min_by_key(|&(i, _)| self.nodes[i].g);
+
+        if reached.is_some() {
+            return reached;
+        }
+
+        // Only the cheapest child of each grid position in the layer is kept, and only those
+        // which survive the truncation are marked visited, so dropped positions stay open
+        let mut children: Vec<Node<M>> = Vec::new();
+        let mut layer: FnvHashMap<_, usize> = FnvHashMap::default();
+        for &parent in &self.layer {
+            let current = &self.nodes[parent];
+            model.on_expand(&current.state);
+            for control in sampler.sample(model, &current.state) {
+                if let Some(child_state) = model.integrate(&current.state, control) {
+                    let g = current.g + model.cost(&current.state, control, &child_state);
+                    let position = child_state.grid_position();
+
+                    match self.grid.get(&position) {
+                        Some(&best) if best <= g => continue,
+                        _ => {}
+                    }
+
+                    let f = g + estimate(model, &child_state, goals);
+                    let child = Node {
+                        state: child_state,
+                        control: control.clone(),
+                        g,
+                        f,
+                        parent: Some(parent),
+                    };
+
+                    match layer.entry(position) {
+                        Entry::Occupied(index) => {
+                            let best = &mut children[*index.get()];
+                            if g < best.g {
+                                *best = child;
+                            }
+                        }
+                        Entry::Vacant(empty) => {
+                            empty.insert(children.len());
+                            children.push(child);
+                        }
+                    }
+                }
+            }
+        }
+
+        children.sort_by_key(|child| child.f);
+        children.truncate(self.width);
+
+        self.layer.clear();
+        for child in children {
+            self.grid.insert(child.state.grid_position(), child.g);
+            self.layer.push(self.nodes.len());
+            self.nodes.push(child);
+        }
+
+        None
+    }
+
+    /// Follow the parents from a node up to the start node
+    fn unwind_trajectory(&self, mut current: usize, goal: usize) -> Trajectory<M> {
+        let cost = self.nodes[current].g;
+        let mut result =
+            vec![(self.nodes[current].state.clone(), self.nodes[current].control.clone())];
+
+        while let Some(parent) = self.nodes[current].parent {
+            current = parent;
+            result.push((
+                self.nodes[current].state.clone(),
+                self.nodes[current].control.clone(),
+            ));
+        }
+
+        result.reverse();
+
+        Trajectory { cost, trajectory: result, goal }
+    }
+
+    /// The most promising node of the current layer
+    fn best(&self) -> Option<usize> {
+        self.layer.iter().cloned().min_by_key(|&i| self.nodes[i].f)
+    }
+}
+
+impl<M, S> Optimizer<M, S> for Beam<M>
+where
+    M: HeuristicModel,
+    M::Cost: Copy,
+    S: Sampler<M>,
+{
+    fn clear(&mut self) {
+        Beam::clear(self)
+    }
+
+    fn next_trajectory(
+        &mut self,
+        model: &mut M,
+        start: &M::State,
+        goal: &M::Goal,
+        sampler: &mut S,
+    ) -> PathResult<M> {
+        use PathFindingErr::*;
+        use PathResult::*;
+
+        let goals = std::slice::from_ref(goal);
+        if !self.searching {
+            self.clear();
+            if let Some(err) = validate(model, start, goals, &self.budget).err() {
+                return Err(err);
+            }
+
+            model.init(start);
+            self.start(model, start, goals);
+            self.searching = true;
+        }
+
+        if let Some((node, reached)) = self.step(model, goals, sampler) {
+            let result = Final(self.unwind_trajectory(node, reached));
+            return conclude(model, result, &mut self.searching);
+        }
+
+        let result = match self.best() {
+            Some(best) => Intermediate(self.unwind_trajectory(best, 0)),
+            None => {
+                Err(Unreachable(Diagnostics { closed: self.grid.len(), ..Default::default() }))
+            }
+        };
+
+        conclude(model, result, &mut self.searching)
+    }
+
+    fn optimize_nearest(
+        &mut self,
+        model: &mut M,
+        start: &M::State,
+        goals: &[M::Goal],
+        sampler: &mut S,
+    ) -> PathResult<M> {
+        use PathResult::*;
+
+        if !self.searching {
+            self.clear();
+            if let Some(err) = validate(model, start, goals, &self.budget).err() {
+                return Err(err);
+            }
+
+            model.init(start);
+            self.start(model, start, goals);
+            self.searching = true;
+        }
+
+        let mut progress = Progress::new();
+
+        while !self.layer.is_empty() {
+            let mut running = true;
+            for &node in &self.layer {
+                running &= progress.expand(
+                    &self.budget,
+                    model,
+                    &self.nodes[node].state,
+                    goals,
+                    || node,
+                );
+            }
+
+            if let Some((node, reached)) = self.step(model, goals, sampler) {
+                let result = Final(self.unwind_trajectory(node, reached));
+                return conclude(model, result, &mut self.searching);
+            }
+
+            if !running {
+                break;
+            }
+        }
+
+        let result = progress
+            .finish(self.grid.len())
+            .map_or_else(Err, |(node, goal)| Partial(self.unwind_trajectory(node, goal)));
+
+        conclude(model, result, &mut self.searching)
+    }
+}
+
+impl<M> Debug for Beam<M>
+where
+    M: HeuristicModel,
+    M::Cost: Copy,
+{
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("Beam")
+            .field("width", &self.width)
+            .field("layer", &self.layer.len())
+            .field("nodes", &self.nodes.len())
+            .field("grid", &self.grid.len())
+            .finish()
+    }
+}
+
+impl<M> Default for Beam<M>
+where
+    M: HeuristicModel,
+    M::Cost: Copy,
+{
+    /// A beam of 64 states
+    fn default() -> Self {
+        Self::new(64)
+    }
+}
@@ -0,0 +1,357 @@
+use super::{Diagnostics, HeuristicModel, Model, PathFindingErr, State};
+
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Mask of the expansion count which is zero every time the clock is checked
+const CLOCK_MASK: usize = 64 - 1;
+
+/// How close a state is to a goal, where lower is closer
+pub type Metric<M> = fn(&M, &<M as Model>::State, &<M as Model>::Goal) -> <M as Model>::Cost;
+
+/// Limits on the work an optimizer may do, and what to return when it gives up
+///
+/// By default a search is unlimited and only returns a trajectory which reaches the goal.
+/// With best effort enabled, the optimizer remembers the expanded state which is the closest
+/// to a goal according to a [`Metric`].  When the goal turns out to be unreachable, or the
+/// search runs out of expansions, it returns [`PathResult::Partial`] with a trajectory to that
+/// state instead of an error, so that an entity can at least get closer.
+///
+/// Best effort wins over a model which rejects a goal as unreachable before searching, for
+/// instance a goal in another connected component: the search still runs, and returns the
+/// partial trajectory.  Other rejections, such as an invalid start, still fail at once.
+///
+/// ```
+/// # use game_lib::map::{Map, Tile};
+/// # use game_lib::Position;
+/// # use std::sync::Arc;
+/// use game_lib::actor::{Actor, Heuristic, TurnOptimal, WalkSampler};
+/// use game_lib::map::{Components, Connectivity};
+/// use game_lib::path::astar::AStar;
+/// use game_lib::path::{Budget, Optimizer, PathFindingErr, PathResult, Target};
+///
+/// // The player is walled off at the end of the corridor
+/// let mut map = Map::new(10, 3);
+/// for x in 1..9 {
+///     map[(x, 1)] = Tile::FLOOR;
+/// }
+/// map[(6, 1)] = Tile::WALL;
+///
+/// let mut model = TurnOptimal::new(map);
+/// model.set_heuristic(Heuristic::Diagonal);
+/// let mut planner = AStar::new();
+/// planner.set_budget(Budget::unlimited().best_effort_heuristic());
+///
+/// let goal = Target::At(Position::new(8, 1));
+/// let start = Actor::new(1, 1, 0, 0);
+/// match planner.optimize(&mut model, &start, &goal, &mut WalkSampler::new()) {
+///     PathResult::Partial(trajectory) => {
+///         assert_eq!(trajectory.trajectory.last().unwrap().0.pos, Position::new(5, 1))
+///     }
+///     _ => panic!("the goal is enclosed"),
+/// }
+///
+/// // Knowing the goal is in another component does not stop a best effort search
+/// model.set_components(Arc::new(Components::new(model.map(), Connectivity::Eight)));
+/// match planner.optimize(&mut model, &start, &goal, &mut WalkSampler::new()) {
+///     PathResult::Partial(trajectory) => assert_eq!(trajectory.cost, 8),
+///     _ => panic!("the search settles for the closest tile"),
+/// }
+///
+/// planner.set_budget(Budget::unlimited());
+/// match planner.optimize(&mut model, &start, &goal, &mut WalkSampler::new()) {
+///     PathResult::Err(PathFindingErr::Unreachable(diagnostics)) => {
+///         assert_eq!(diagnostics.expansions, 0)
+///     }
+///     _ => panic!("the components reject the goal"),
+/// }
+/// ```
+///
+/// A search can also be given a time limit, or be cancelled from another thread with a
+/// [`CancelToken`], in which case it fails with [`PathFindingErr::Timeout`] or
+/// [`PathFindingErr::Cancelled`] unless best effort is enabled.
+///
+/// [`Metric`]: type.Metric.html
+/// [`PathResult::Partial`]: enum.PathResult.html#variant.Partial
+/// [`CancelToken`]: struct.CancelToken.html
+/// [`PathFindingErr::Timeout`]: enum.PathFindingErr.html#variant.Timeout
+/// [`PathFindingErr::Cancelled`]: enum.PathFindingErr.html#variant.Cancelled
+pub struct Budget<M>
+where
+    M: Model,
+{
+    expansions: Option<usize>,
+    timeout: Option<Duration>,
+    cancel: Option<CancelToken>,
+    best_effort: Option<Metric<M>>,
+}
+
+impl<M> Budget<M>
+where
+    M: Model,
+{
+    pub fn unlimited() -> Self {
+        Budget { expansions: None, timeout: None, cancel: None, best_effort: None }
+    }
+
+    /// Give up after expanding a number of states
+    ///
+    /// ```
+    /// # use game_lib::map::{Map, Tile};
+    /// # use game_lib::Position;
+    /// use game_lib::nav::{Grid, Octile};
+    /// use game_lib::path::astar::AStar;
+    /// use game_lib::path::{Budget, Optimizer, PathFindingErr, PathResult, Target};
+    ///
+    /// let mut map = Map::new(32, 3);
+    /// for x in 1..31 {
+    ///     map[(x, 1)] = Tile::FLOOR;
+    /// }
+    ///
+    /// let start = Position::new(1, 1);
+    /// let goal = Target::At(Position::new(30, 1));
+    /// let mut planner = AStar::new();
+    /// planner.set_budget(Budget::unlimited().expansions(5));
+    /// match planner.optimize(&mut Grid::new(&map), &start, &goal, &mut Octile) {
+    ///     PathResult::Err(PathFindingErr::IterationLimit(expanded)) => assert_eq!(expanded, 5),
+    ///     _ => panic!("the corridor is longer than the budget"),
+    /// }
+    ///
+    /// // With best effort, the search returns the furthest it got down the corridor
+    /// planner.set_budget(Budget::unlimited().expansions(5).best_effort_heuristic());
+    /// match planner.optimize(&mut Grid::new(&map), &start, &goal, &mut Octile) {
+    ///     PathResult::Partial(trajectory) => {
+    ///         assert_eq!(trajectory.trajectory.last().unwrap().0, Position::new(5, 1))
+    ///     }
+    ///     _ => panic!("the budget runs out before the goal"),
+    /// }
+    /// ```
+    pub fn expansions(mut self, expansions: usize) -> Self {
+        self.expansions = Some(expansions);
+        self
+    }
+
+    /// Give up after searching for some time
+    ///
+    /// The clock is only checked every few expansions, so a search may run slightly longer.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    /// Give up once the token is cancelled
+    pub fn cancel_on(mut self, token: CancelToken) -> Self {
+        self.cancel = Some(token);
+        self
+    }
+
+    /// Return the path to the closest state according to `metric` when giving up
+    pub fn best_effort(mut self, metric: Metric<M>) -> Self {
+        self.best_effort = Some(metric);
+        self
+    }
+
+    pub fn max_expansions(&self) -> Option<usize> {
+        self.expansions
+    }
+
+    pub fn max_duration(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    pub fn metric(&self) -> Option<Metric<M>> {
+        self.best_effort
+    }
+
+    /// Test if a search must give up, and why
+    fn exhausted(&self, expanded: usize, started: Instant) -> Option<Stop> {
+        if self.expansions.map(|limit| expanded >= limit).unwrap_or(false) {
+            Some(Stop::Expansions)
+        } else if self.cancel.as_ref().map(CancelToken::is_cancelled).unwrap_or(false) {
+            Some(Stop::Cancelled)
+        } else if expanded & CLOCK_MASK == 0
+            && self.timeout.map(|timeout| started.elapsed() >= timeout).unwrap_or(false)
+        {
+            Some(Stop::Timeout)
+        } else {
+            None
+        }
+    }
+}
+
+impl<M> Budget<M>
+where
+    M: HeuristicModel,
+{
+    /// Return the path to the state with the lowest heuristic when giving up
+    pub fn best_effort_heuristic(self) -> Self {
+        self.best_effort(heuristic::<M>)
+    }
+}
+
+fn heuristic<M>(model: &M, state: &M::State, goal: &M::Goal) -> M::Cost
+where
+    M: HeuristicModel,
+{
+    model.heuristic(state, goal)
+}
+
+impl<M> Default for Budget<M>
+where
+    M: Model,
+{
+    fn default() -> Self {
+        Self::unlimited()
+    }
+}
+
+impl<M> Clone for Budget<M>
+where
+    M: Model,
+{
+    fn clone(&self) -> Self {
+        Budget {
+            expansions: self.expansions,
+            timeout: self.timeout,
+            cancel: self.cancel.clone(),
+            best_effort: self.best_effort,
+        }
+    }
+}
+
+impl<M> Debug for Budget<M>
+where
+    M: Model,
+{
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Budget")
+            .field("expansions", &self.expansions)
+            .field("timeout", &self.timeout)
+            .field("cancel", &self.cancel)
+            .field("best_effort", &self.best_effort.is_some())
+            .finish()
+    }
+}
+
+/// A flag to cancel searches from another thread
+///
+/// Clones of a token share the same flag, so a clone can be handed to the [`Budget`] of a
+/// search while the original is kept to cancel it.
+///
+/// [`Budget`]: struct.Budget.html
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel every search using the token
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    /// Allow the token to be used for new searches
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed)
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Why a search gave up
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Stop {
+    Expansions,
+    Cancelled,
+    Timeout,
+}
+
+/// The work done by a search, and the node closest to a goal among the nodes expanded
+pub(crate) struct Progress<M, N>
+where
+    M: Model,
+{
+    best: Option<(M::Cost, usize, N)>,
+    diagnostics: Diagnostics,
+    started: Instant,
+    stop: Option<Stop>,
+}
+
+impl<M, N> Progress<M, N>
+where
+    M: Model,
+{
+    pub fn new() -> Self {
+        Progress {
+            best: None,
+            diagnostics: Diagnostics::default(),
+            started: Instant::now(),
+            stop: None,
+        }
+    }
+
+    /// Record the expansion of a state
+    ///
+    /// The node is only created when it is closer to any goal than the closest node so far.
+    /// Returns `false` when the search must give up.
+    pub fn expand<F>(
+        &mut self,
+        budget: &Budget<M>,
+        model: &M,
+        state: &M::State,
+        goals: &[M::Goal],
+        node: F,
+    ) -> bool
+    where
+        F: FnOnce() -> N,
+    {
+        if let Some(metric) = budget.best_effort {
+            let closest = goals
+                .iter()
+                .enumerate()
+                .map(|(i, goal)| (metric(model, state, goal), i))
+                .min_by(|a, b| a.0.cmp(&b.0));
+
+            if let Some((distance, goal)) = closest {
+                if self.best.as_ref().map(|best| distance < best.0).unwrap_or(true) {
+                    self.best = Some((distance, goal, node()));
+                }
+            }
+        }
+
+        if let Some(tile) = state.tile() {
+            self.diagnostics.include(&tile);
+        }
+
+        self.diagnostics.expansions += 1;
+        if self.stop.is_none() {
+            self.stop = budget.exhausted(self.diagnostics.expansions, self.started);
+        }
+        self.stop.is_none()
+    }
+
+    /// The closest node and the index of the goal it is closest to, or why the search failed
+    ///
+    /// `closed` is the number of positions the search discovered.
+    pub fn finish(mut self, closed: usize) -> Result<(N, usize), PathFindingErr> {
+        if let Some((_, goal, node)) = self.best {
+            return Ok((node, goal));
+        }
+
+        self.diagnostics.closed = closed;
+        Err(match self.stop {
+            None => PathFindingErr::Unreachable(self.diagnostics),
+            Some(Stop::Expansions) => {
+                PathFindingErr::IterationLimit(self.diagnostics.expansions)
+            }
+            Some(Stop::Cancelled) => PathFindingErr::Cancelled(self.diagnostics),
+            Some(Stop::Timeout) => PathFindingErr::Timeout(self.diagnostics),
+        })
+    }
+}
@@ -1,4 +1,9 @@
-use super::{Model, Optimizer, PathFindingErr, PathResult, Sampler, State, Trajectory};
+use super::budget::{Budget, Progress};
+use super::dominance::Labels;
+use super::{
+    conclude, validate, Diagnostics, Dominance, Model, Optimizer, PathFindingErr, PathResult,
+    Sampler, State, Trajectory,
+};
 use fnv::FnvHashMap;
 use radix_heap::{Radix, RadixHeapMap};
 
@@ -7,6 +12,53 @@ use std::collections::hash_map::Entry;
 use std::fmt::{self, Debug, Formatter};
 use std::hash::{self, Hash};
 
+/// Uniform cost search, which expands states in order of the cost to reach them
+///
+/// Without a heuristic, the search explores every direction evenly until it reaches a goal.
+/// The trajectories it returns run from the start to the state reached, like those of the
+/// other optimizers.
+///
+/// ```
+/// # use game_lib::map::{Map, Tile};
+/// # use game_lib::Position;
+/// use game_lib::actor::{Actor, TurnOptimal, WalkSampler};
+/// use game_lib::path::dijkstra::Dijkstra;
+/// use game_lib::path::{Budget, Optimizer, PathResult, Target};
+///
+/// let mut map = Map::new(10, 3);
+/// for x in 1..9 {
+///     map[(x, 1)] = Tile::FLOOR;
+/// }
+/// map[(6, 1)] = Tile::WALL;
+///
+/// let mut model = TurnOptimal::new(map);
+/// let mut planner = Dijkstra::default();
+/// planner.set_budget(Budget::unlimited().best_effort_heuristic());
+/// let start = Actor::new(1, 1, 0, 0);
+///
+/// let positions = |trajectory: &[(Actor, _)]| {
+///     trajectory.iter().map(|(actor, _)| actor.pos.x).collect::<Vec<_>>()
+/// };
+///
+/// let goal = Target::At(Position::new(4, 1));
+/// match planner.optimize(&mut model, &start, &goal, &mut WalkSampler::new()) {
+///     PathResult::Final(trajectory) => {
+///         assert_eq!(positions(&trajectory.trajectory), [1, 2, 3, 4]);
+///         assert_eq!(trajectory.cost, 6);
+///     }
+///     _ => panic!("the goal is in the open"),
+/// }
+///
+/// // Past the wall, the search settles for the closest tile
+/// let goal = Target::At(Position::new(8, 1));
+/// match planner.optimize(&mut model, &start, &goal, &mut WalkSampler::new()) {
+///     PathResult::Partial(trajectory) => {
+///         assert_eq!(positions(&trajectory.trajectory), [1, 2, 3, 4, 5]);
+///         assert_eq!(trajectory.cost, 8);
+///     }
+///     _ => panic!("the goal is enclosed"),
+/// }
+/// ```
 pub struct Dijkstra<M>
 where
     M: Model,
@@ -16,6 +68,9 @@ where
     grid: FnvHashMap<<<M as Model>::State as State>::Position, Id<M>>,
     parent_map: FnvHashMap<Id<M>, Node<M>>,
     id_counter: usize,
+    labels: Labels<M>,
+    budget: Budget<M>,
+    searching: bool,
 }
 
 impl<M> Default for Dijkstra<M>
@@ -29,6 +84,9 @@ where
             grid: Default::default(),
             parent_map: Default::default(),
             id_counter: 0,
+            labels: Labels::new(),
+            budget: Budget::unlimited(),
+            searching: false,
         }
     }
 }
@@ -38,10 +96,47 @@ where
     M: Model,
     M::Cost: Radix + Copy,
 {
+    /// Create a new optimizer with room to discover `states` states without allocating
+    ///
+    /// The radix heap cannot reserve memory up front, so its buckets still grow during the
+    /// first searches.
+    pub fn with_capacity(states: usize) -> Self {
+        Dijkstra {
+            grid: FnvHashMap::with_capacity_and_hasher(states, Default::default()),
+            parent_map: FnvHashMap::with_capacity_and_hasher(states, Default::default()),
+            ..Default::default()
+        }
+    }
+
+    /// Forget the previous search, keeping the memory it used for the following searches
     pub fn clear(&mut self) {
         self.queue.clear();
         self.parent_map.clear();
         self.grid.clear();
+        self.labels.clear();
+        self.id_counter = 0;
+        self.searching = false;
+    }
+
+    /// Keep every state which no other state at its grid position dominates
+    ///
+    /// See [`Dominance`].
+    ///
+    /// [`Dominance`]: ../trait.Dominance.html
+    pub fn use_dominance(&mut self)
+    where
+        M::State: Dominance,
+    {
+        self.labels.enable(<M::State as Dominance>::dominates)
+    }
+
+    pub fn budget(&self) -> &Budget<M> {
+        &self.budget
+    }
+
+    /// Limit the work of the following searches, and what they return when giving up
+    pub fn set_budget(&mut self, budget: Budget<M>) {
+        self.budget = budget
     }
 
     pub fn inspect_queue(&self) -> impl Iterator<Item = (&M::State, &M::Control)> {
@@ -54,21 +149,82 @@ where
         self.grid.keys()
     }
 
+    /// Explore every state reachable from a set of weighted sources, without any goal
+    ///
+    /// Each source starts with its own cost, so a source with a higher cost is less
+    /// attractive than the others.  Once the flood is over, [`cost`] is the cost of the
+    /// cheapest path from any of the sources to a grid position.
+    ///
+    /// Any previous search is cleared first.  A flood has no single start, so the model only
+    /// hears of the states expanded through [`on_expand`].
+    ///
+    /// [`cost`]: #method.cost
+    /// [`on_expand`]: ../trait.Model.html#method.on_expand
+    pub fn flood<S, I>(&mut self, model: &mut M, sources: I, sampler: &mut S)
+    where
+        S: Sampler<M>,
+        I: IntoIterator<Item = (M::State, M::Cost)>,
+    {
+        self.clear();
+
+        for (state, cost) in sources {
+            self.id_counter += 1;
+            let source = Node::<M> {
+                id: Id::new(self.id_counter, cost),
+                state,
+                control: Default::default(),
+            };
+
+            match self.grid.entry(source.state.grid_position()) {
+                Entry::Occupied(mut best) => {
+                    if best.get().g.0 <= cost {
+                        continue;
+                    }
+                    best.insert(source.id.clone());
+                }
+                Entry::Vacant(empty) => {
+                    empty.insert(source.id.clone());
+                }
+            }
+
+            self.queue.push(source.id.g, source);
+        }
+
+        while let Some(current) = self.pop() {
+            self.step(&current, model, &[], sampler);
+        }
+    }
+
+    /// Cost of the cheapest path discovered so far to a grid position
+    pub fn cost(&self, pos: &<<M as Model>::State as State>::Position) -> Option<M::Cost> {
+        self.grid.get(pos).map(|id| id.g.0)
+    }
+
+    /// Every grid position discovered so far with the cost of the cheapest path to it
+    pub fn costs(
+        &self,
+    ) -> impl Iterator<Item = (&<<M as Model>::State as State>::Position, M::Cost)> {
+        self.grid.iter().map(|(pos, id)| (pos, id.g.0))
+    }
+
     #[inline(always)]
     fn step<S>(
         &mut self,
         current: &Node<M>,
         model: &mut M,
-        goal: &M::State,
+        goals: &[M::Goal],
         sampler: &mut S,
-    ) -> bool
+    ) -> Option<usize>
     where
         S: Sampler<M>,
     {
-        if model.converge(&current.state, goal) {
-            return true;
+        if let Some(reached) =
+            goals.iter().position(|goal| model.converge(&current.state, goal))
+        {
+            return Some(reached);
         }
 
+        model.on_expand(&current.state);
         for control in sampler.sample(model, &current.state) {
             if let Some(child_state) = model.integrate(&current.state, &control) {
                 self.id_counter += 1;
@@ -81,13 +237,21 @@ where
                     control: control.clone(),
                 };
 
+                // Compared by dominance, a costlier state is kept unless a label dominates it
+                let dominant = self.labels.admit(&child.state, cost, child.id.id);
+                if dominant == Some(false) {
+                    continue;
+                }
+
                 let position = self.grid.entry(child.state.grid_position());
 
                 match position {
                     Entry::Occupied(mut best) => {
                         let best = best.get_mut();
                         if best.g.0 <= child.id.g.0 {
-                            continue;
+                            if dominant.is_none() {
+                                continue;
+                            }
                         } else {
                             *best = child.id.clone();
                         }
@@ -102,11 +266,23 @@ where
             }
         }
 
-        false
+        None
+    }
+
+    /// Take the next node off the queue, dropping the nodes pruned by dominance
+    fn pop(&mut self) -> Option<Node<M>> {
+        while let Some((_, node)) = self.queue.pop() {
+            if !self.labels.take_pruned(node.id.id) {
+                return Some(node);
+            }
+            self.parent_map.remove(&node.id);
+        }
+        None
     }
 
-    fn unwind_trajectory(&self, mut current: Node<M>) -> Trajectory<M> {
-        let mut result = Vec::new();
+    fn unwind_trajectory(&self, mut current: Node<M>, goal: usize) -> Trajectory<M> {
+        let cost = current.id.g.0;
+        let mut result = Vec::with_capacity(self.depth(&current));
         result.push((current.state.clone(), current.control.clone()));
 
         while let Some(p) = self.parent_map.get(&current.id) {
@@ -114,7 +290,20 @@ where
             result.push((current.state.clone(), current.control.clone()));
         }
 
-        Trajectory { cost: current.id.g.0, trajectory: result }
+        result.reverse();
+
+        Trajectory { cost, trajectory: result, goal }
+    }
+
+    /// The number of nodes from a node up to a source
+    fn depth(&self, node: &Node<M>) -> usize {
+        let mut depth = 1;
+        let mut current = &node.id;
+        while let Some(parent) = self.parent_map.get(current) {
+            current = &parent.id;
+            depth += 1;
+        }
+        depth
     }
 }
 
@@ -124,67 +313,105 @@ where
     M::Cost: Copy + Radix,
     S: Sampler<M>,
 {
-    fn optimize(
+    fn clear(&mut self) {
+        Dijkstra::clear(self)
+    }
+
+    fn optimize_nearest(
         &mut self,
         model: &mut M,
         start: &M::State,
-        goal: &M::State,
+        goals: &[M::Goal],
         sampler: &mut S,
     ) -> PathResult<M> {
-        use PathFindingErr::*;
         use PathResult::*;
 
-        if model.converge(start, goal) {
-            return Final(Trajectory {
-                cost: Default::default(),
-                trajectory: vec![(start.clone(), Default::default())],
-            });
-        }
+        if !self.searching {
+            self.clear();
+            if let Some(reached) = goals.iter().position(|goal| model.converge(start, goal)) {
+                model.init(start);
+                return conclude(
+                    model,
+                    Final(Trajectory {
+                        cost: Default::default(),
+                        trajectory: vec![(start.clone(), Default::default())],
+                        goal: reached,
+                    }),
+                    &mut self.searching,
+                );
+            }
 
-        if self.queue.top().is_none() {
+            if let Some(err) = validate(model, start, goals, &self.budget).err() {
+                return Err(err);
+            }
+
+            model.init(start);
             let start_id = Id::new(0, Default::default());
             self.queue.push(
                 Default::default(),
                 Node { id: start_id, state: start.clone(), control: Default::default() },
             );
+            self.searching = true;
         }
 
-        while let Some((_, current)) = self.queue.pop() {
-            if self.step(&current, model, &goal, sampler) {
-                return Final(self.unwind_trajectory(current));
+        let mut progress = Progress::new();
+
+        while let Some(current) = self.pop() {
+            if let Some(reached) = self.step(&current, model, goals, sampler) {
+                let result = Final(self.unwind_trajectory(current, reached));
+                return conclude(model, result, &mut self.searching);
+            }
+
+            if !progress.expand(&self.budget, model, &current.state, goals, || current.clone())
+            {
+                break;
             }
         }
 
-        Err(Unreachable)
+        let result = progress
+            .finish(self.grid.len())
+            .map_or_else(Err, |(node, goal)| Partial(self.unwind_trajectory(node, goal)));
+
+        conclude(model, result, &mut self.searching)
     }
 
     fn next_trajectory(
         &mut self,
         model: &mut M,
         start: &M::State,
-        goal: &M::State,
+        goal: &M::Goal,
         sampler: &mut S,
     ) -> PathResult<M> {
         use PathFindingErr::*;
         use PathResult::*;
 
-        if self.parent_map.is_empty() && self.queue.is_empty() {
+        if !self.searching {
+            self.clear();
+            if let Some(err) =
+                validate(model, start, std::slice::from_ref(goal), &self.budget).err()
+            {
+                return Err(err);
+            }
+
+            model.init(start);
             let start_id = Id::new(0, Default::default());
             self.queue.push(
                 Default::default(),
                 Node { id: start_id, state: start.clone(), control: Default::default() },
             );
+            self.searching = true;
         }
 
-        if let Some((_, current)) = self.queue.pop() {
-            if self.step(&current, model, &goal, sampler) {
-                Final(self.unwind_trajectory(current))
-            } else {
-                Intermediate(self.unwind_trajectory(current))
+        let result = if let Some(current) = self.pop() {
+            match self.step(&current, model, std::slice::from_ref(goal), sampler) {
+                Some(reached) => Final(self.unwind_trajectory(current, reached)),
+                None => Intermediate(self.unwind_trajectory(current, 0)),
             }
         } else {
-            Err(Unreachable)
-        }
+            Err(Unreachable(Diagnostics { closed: self.grid.len(), ..Default::default() }))
+        };
+
+        conclude(model, result, &mut self.searching)
     }
 }
 
@@ -0,0 +1,85 @@
+use fnv::{FnvHashMap, FnvHashSet};
+
+use super::{Model, State};
+
+/// Test if a state dominates another
+type Dominates<M> = fn(&<M as Model>::State, &<M as Model>::State) -> bool;
+
+/// The labels of a grid position, with the cost to reach each one and the id of its node
+type Set<M> = Vec<(<M as Model>::State, <M as Model>::Cost, usize)>;
+
+/// The states discovered at each grid position which no other state dominates
+///
+/// Only kept once the optimizer is told to compare states by [`Dominance`], otherwise the
+/// optimizer keeps the cheapest state of each grid position on its own.
+///
+/// The nodes of labels dominated after they were queued are remembered as pruned, so that
+/// the optimizer drops them when they come out of the queue instead of expanding them.
+///
+/// [`Dominance`]: trait.Dominance.html
+pub(super) struct Labels<M>
+where
+    M: Model,
+{
+    dominates: Option<Dominates<M>>,
+    labels: FnvHashMap<<<M as Model>::State as State>::Position, Set<M>>,
+    pruned: FnvHashSet<usize>,
+}
+
+impl<M> Labels<M>
+where
+    M: Model,
+    M::Cost: Copy,
+{
+    pub fn new() -> Self {
+        Labels {
+            dominates: None,
+            labels: FnvHashMap::default(),
+            pruned: FnvHashSet::default(),
+        }
+    }
+
+    /// Compare the states at each grid position with a dominance relation
+    pub fn enable(&mut self, dominates: Dominates<M>) {
+        self.dominates = Some(dominates)
+    }
+
+    /// Forget every label, keeping the memory of each grid position for the next search
+    pub fn clear(&mut self) {
+        for labels in self.labels.values_mut() {
+            labels.clear();
+        }
+        self.pruned.clear();
+    }
+
+    /// Keep the state of node `id` reached at cost `g` unless it is dominated, pruning the
+    /// nodes of the labels it dominates
+    ///
+    /// Returns `None` when states are not compared by dominance.
+    pub fn admit(&mut self, state: &M::State, g: M::Cost, id: usize) -> Option<bool> {
+        let dominates = self.dominates?;
+        let labels = self.labels.entry(state.grid_position()).or_default();
+
+        if labels.iter().any(|(label, cost, _)| *cost <= g && dominates(label, state)) {
+            return Some(false);
+        }
+
+        let pruned = &mut self.pruned;
+        labels.retain(|(label, cost, node)| {
+            let dominated = g <= *cost && dominates(state, label);
+            if dominated {
+                pruned.insert(*node);
+            }
+            !dominated
+        });
+        labels.push((state.clone(), g, id));
+        Some(true)
+    }
+
+    /// Test if the node `id` was pruned since it was queued, forgetting it either way
+    ///
+    /// Each node leaves the queue once, so its id is not needed afterwards.
+    pub fn take_pruned(&mut self, id: usize) -> bool {
+        self.pruned.remove(&id)
+    }
+}
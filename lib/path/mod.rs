@@ -29,8 +29,23 @@ use std::fmt::Debug;
 use std::hash::Hash;
 use std::ops::Add;
 
+use crate::{Position, Rect};
+
 pub mod astar;
+pub mod batch;
+pub mod beam;
+mod budget;
 pub mod dijkstra;
+mod dominance;
+pub mod multi;
+pub mod pool;
+pub mod sampler;
+pub mod spacetime;
+mod target;
+pub mod theta;
+
+pub use self::budget::{Budget, CancelToken, Metric};
+pub use self::target::Target;
 
 /// Marker trait which is required for the type which a [`Model`] uses to represent costs.
 ///
@@ -58,12 +73,179 @@ pub trait State {
     type Position: Eq + Hash + Debug;
 
     fn grid_position(&self) -> Self::Position;
+
+    /// The map tile the state is on, if it lies on a map
+    ///
+    /// Used to report the region a failed search explored in its [`Diagnostics`].
+    ///
+    /// [`Diagnostics`]: struct.Diagnostics.html
+    fn tile(&self) -> Option<Position> {
+        None
+    }
+}
+
+/// States which may be worth keeping besides the cheapest one at their grid position
+///
+/// Optimizers keep a single state per grid position, the cheapest one found, unless told to
+/// compare states by dominance.  States which carry more than their position, such as the
+/// mana of an actor, may then keep several _labels_ per grid position: a state is dropped
+/// only when another one at its position dominates it and was reached at no higher cost.
+///
+/// ```
+/// # use game_lib::map::{Map, Tile};
+/// # use game_lib::Position;
+/// use game_lib::actor::{Actor, TeleportSampler, TurnOptimal};
+/// use game_lib::path::{astar::AStar, Dominance, Optimizer, PathResult, Target};
+///
+/// let mut map = Map::new(40, 3);
+/// for x in 1..39 {
+///     map[(x, 1)] = Tile::FLOOR;
+/// }
+///
+/// let full = Actor::new(5, 1, 10, 10);
+/// assert!(full.dominates(&Actor::new(5, 1, 3, 10)));
+/// assert!(!full.dominates(&Actor::new(6, 1, 3, 10)));
+///
+/// let mut model = TurnOptimal::new(map);
+/// let mut planner = AStar::new();
+/// planner.use_dominance();
+///
+/// let goal = Target::At(Position::new(35, 1));
+/// match planner.optimize(&mut model, &full, &goal, &mut TeleportSampler::new()) {
+///     PathResult::Final(trajectory) => assert!(trajectory.cost < 2 * 30),
+///     _ => panic!("the corridor is open"),
+/// }
+/// ```
+pub trait Dominance: State {
+    /// Test if the state is at least as good as another one at the same grid position
+    ///
+    /// The cost to reach either state is compared by the optimizer, so only the rest of
+    /// the state matters here.  States dominate themselves.
+    fn dominates(&self, other: &Self) -> bool;
 }
 
 /// Interface which defines the problem
 ///
 /// The model defines how costs are estimated and calculated, the mapping between controls and
 /// states, and the validity and termination conditions of our problem.
+///
+/// # Lifecycle
+///
+/// The optimizers call the hooks of the model in order during each search:
+///
+/// 1. [`validate`] rejects the problem before anything else, in which case no other hook runs
+/// 2. [`init`] runs once with the start state, before any state is expanded
+/// 3. [`on_expand`] runs for every state expanded, before its controls are sampled
+/// 4. [`on_solution`] runs with the trajectory of a [`Final`] result, before it is returned
+/// 5. [`finish`] runs once the search ends, whatever its result
+///
+/// A search runs from the call which starts it until a call returns anything other than an
+/// [`Intermediate`] trajectory.  Until then, both [`next_trajectory`] and [`optimize`]
+/// continue it with the start and goals it began with, so a search stepped through and then
+/// finished by [`optimize`] still calls [`init`] and [`finish`] once.  The next call after
+/// the search ends starts a new one, whether or not the optimizer was cleared.
+///
+/// ```
+/// use game_lib::path::astar::AStar;
+/// use game_lib::path::{HeuristicModel, Model, Optimizer, PathResult, Sampler, State, Trajectory};
+///
+/// #[derive(Debug, Clone)]
+/// struct Cell(i32);
+///
+/// impl State for Cell {
+///     type Position = i32;
+///
+///     fn grid_position(&self) -> i32 {
+///         self.0
+///     }
+/// }
+///
+/// /// Walk along a line, recording the hooks as they run
+/// #[derive(Default)]
+/// struct Line {
+///     hooks: Vec<String>,
+/// }
+///
+/// impl Model for Line {
+///     type State = Cell;
+///     type Control = i32;
+///     type Cost = usize;
+///     type Goal = i32;
+///
+///     fn cost(&self, _: &Cell, _: &i32, _: &Cell) -> usize {
+///         1
+///     }
+///
+///     fn init(&mut self, initial: &Cell) {
+///         self.hooks.push(format!("init {}", initial.0));
+///     }
+///
+///     fn on_expand(&mut self, state: &Cell) {
+///         self.hooks.push(format!("expand {}", state.0));
+///     }
+///
+///     fn on_solution(&mut self, trajectory: &Trajectory<Self>) {
+///         self.hooks.push(format!("solution {}", trajectory.cost));
+///     }
+///
+///     fn finish(&mut self) {
+///         self.hooks.push("finish".to_string());
+///     }
+///
+///     fn converge(&self, current: &Cell, goal: &i32) -> bool {
+///         current.0 == *goal
+///     }
+///
+///     fn integrate(&self, previous: &Cell, step: &i32) -> Option<Cell> {
+///         Some(Cell(previous.0 + step))
+///     }
+/// }
+///
+/// impl HeuristicModel for Line {
+///     fn heuristic(&self, current: &Cell, goal: &i32) -> usize {
+///         (goal - current.0).abs() as usize
+///     }
+/// }
+///
+/// struct Forward;
+///
+/// impl Sampler<Line> for Forward {
+///     fn sample(&mut self, _: &Line, _: &Cell) -> &[i32] {
+///         &[1]
+///     }
+/// }
+///
+/// let mut line = Line::default();
+/// AStar::new().optimize(&mut line, &Cell(0), &2, &mut Forward);
+/// assert_eq!(line.hooks, ["init 0", "expand 0", "expand 1", "solution 2", "finish"]);
+///
+/// // Finish a search started one step at a time, then plan again without clearing
+/// let mut line = Line::default();
+/// let mut planner = AStar::new();
+/// planner.next_trajectory(&mut line, &Cell(0), &2, &mut Forward);
+/// match planner.optimize(&mut line, &Cell(0), &2, &mut Forward) {
+///     PathResult::Final(trajectory) => assert_eq!(trajectory.cost, 2),
+///     _ => panic!("the goal is ahead"),
+/// }
+/// planner.optimize(&mut line, &Cell(5), &6, &mut Forward);
+/// assert_eq!(
+///     line.hooks,
+///     [
+///         "init 0", "expand 0", "expand 1", "solution 2", "finish",
+///         "init 5", "expand 5", "solution 1", "finish",
+///     ]
+/// );
+/// ```
+///
+/// [`validate`]: #method.validate
+/// [`init`]: #tymethod.init
+/// [`on_expand`]: #method.on_expand
+/// [`on_solution`]: #method.on_solution
+/// [`finish`]: #method.finish
+/// [`Final`]: enum.PathResult.html#variant.Final
+/// [`Intermediate`]: enum.PathResult.html#variant.Intermediate
+/// [`next_trajectory`]: trait.Optimizer.html#tymethod.next_trajectory
+/// [`optimize`]: trait.Optimizer.html#method.optimize
 pub trait Model {
     /// The state of the system as a result of actions taken
     type State: Debug + Clone + State;
@@ -79,6 +261,15 @@ pub trait Model {
     /// [`Optimizer`]: /path/trait.Optimizer.html
     type Cost: Debug + Clone + Cost;
 
+    /// The goal which planning converges to
+    ///
+    /// Goals are separate from states so that a goal only has to describe what it means to
+    /// be done, for example reaching a position, rather than a whole state.  See [`Target`]
+    /// for goals on grid positions.
+    ///
+    /// [`Target`]: enum.Target.html
+    type Goal: Debug;
+
     /// Determine the cost between two states
     ///
     /// Given a current state and future state, to find the optimal path in terms of a
@@ -104,25 +295,76 @@ pub trait Model {
 
     /// Read and set initial conditions
     ///
-    /// Called once planning starts, giving the model access to the first state to perform a
-    /// sometimes necessary initialization step, such as:
+    /// Called once planning starts, after the problem passed [`validate`] and before any state
+    /// is expanded, giving the model access to the first state to perform a sometimes
+    /// necessary initialization step, such as:
     ///
     /// - removing obstacles close to the initial state
     /// - calculating extra initial values for the model that depend on the values of the first
     ///   state
+    ///
+    /// [`validate`]: #method.validate
     fn init(&mut self, initial: &Self::State);
 
+    /// Called for every state the optimizer expands, before sampling its controls
+    ///
+    /// States which reach a goal are not expanded.  By default nothing happens.
+    fn on_expand(&mut self, _state: &Self::State) {}
+
+    /// Called with the trajectory which reaches a goal, before the optimizer returns it
+    ///
+    /// By default nothing happens.
+    fn on_solution(&mut self, _trajectory: &Trajectory<Self>)
+    where
+        Self: Sized,
+    {
+    }
+
+    /// Called once the search which called [`init`] ends, whether it failed or not
+    ///
+    /// Data kept for a single search can be dropped here.  By default nothing happens.
+    ///
+    /// [`init`]: #tymethod.init
+    fn finish(&mut self) {}
+
     /// Termination or convergence condition testing
     ///
     /// Test the current State against the goal to determine if it meets the
-    /// convergence criteron against the goal State.
+    /// convergence criteron of the goal.
     ///
     /// The `current` [`State`] is tested against for convergence.
-    /// The `goal` [`State`] represents the solution.
+    /// The `goal` [`Goal`] represents the solution.
     /// Returns `true` when a solution is found
     ///
     /// [`State`]: #associatedtype.State
-    fn converge(&self, current: &Self::State, goal: &Self::State) -> bool;
+    /// [`Goal`]: #associatedtype.Goal
+    fn converge(&self, current: &Self::State, goal: &Self::Goal) -> bool;
+
+    /// Reject a problem which has no solution before searching
+    ///
+    /// Called by the optimizers before a search starts, with every goal of the search.  A
+    /// search fails at once with the error if the start is rejected, or if every goal is,
+    /// instead of exploring every state it can reach to prove the goal unreachable.  Return
+    /// [`StartInvalid`], [`GoalBlocked`] or [`GoalOutOfBounds`] with empty diagnostics.
+    ///
+    /// A goal may also be rejected as [`Unreachable`], when the model knows it cannot be
+    /// reached from the start.  When the [`Budget`] of the search asks for best effort, the
+    /// search runs anyway and returns a partial trajectory toward such a goal instead.
+    ///
+    /// By default every problem is accepted.
+    ///
+    /// [`Unreachable`]: enum.PathFindingErr.html#variant.Unreachable
+    /// [`Budget`]: struct.Budget.html
+    /// [`StartInvalid`]: enum.PathFindingErr.html#variant.StartInvalid
+    /// [`GoalBlocked`]: enum.PathFindingErr.html#variant.GoalBlocked
+    /// [`GoalOutOfBounds`]: enum.PathFindingErr.html#variant.GoalOutOfBounds
+    fn validate(
+        &self,
+        _start: &Self::State,
+        _goal: &Self::Goal,
+    ) -> Result<(), PathFindingErr> {
+        Ok(())
+    }
 
     /// Generate a new current state from a control which is applied to a previous state
     ///
@@ -152,16 +394,17 @@ pub trait HeuristicModel: Model {
     /// Estimate of future costs from the current state
     ///
     /// - `current` the state to traverse from
-    /// - `goal` the overall goal node to estimate the future costs from
+    /// - `goal` the overall goal to estimate the future costs to
     ///
-    /// Given the current state and the goal state, what can we estimate the future costs will
+    /// Given the current state and the goal, what can we estimate the future costs will
     /// be? The heuristic determines where the most fertile paths to search exist, assuming
     /// that continuing along a direct path to the goal will result in the most efficient
     /// overall solution.  This ensures that paths which take a less direct route are
     /// explored last.
     ///
     /// The canonical heuristic function is often also the euclidian distance from the current
-    /// state to the goal state.
+    /// state to the goal.  When the goal is a region, the distance to the closest point of the
+    /// region keeps the estimate optimistic.
     ///
     /// The heuristic works best when its units are the same--or at least in the same order of
     /// magnitude--as the cost.
@@ -170,27 +413,95 @@ pub trait HeuristicModel: Model {
     /// is, the heuristic **must never over-estimate the cost** of a future path.
     /// Over-estimation breaks optimality guarantees. Furthermore the heuristic must never
     /// return a negative value.
-    fn heuristic(&self, current: &Self::State, goal: &Self::State) -> Self::Cost;
+    fn heuristic(&self, current: &Self::State, goal: &Self::Goal) -> Self::Cost;
 }
 
+/// Models where states can reach each other in a straight line, skipping the steps between
+///
+/// Any-angle optimizers such as [`ThetaStar`] link a state directly to an earlier state in
+/// sight of it, rather than to the state it was sampled from.
+///
+/// [`ThetaStar`]: theta/struct.ThetaStar.html
+pub trait LineOfSight: HeuristicModel {
+    /// Test if nothing stands on the straight line from one state to another
+    fn line_of_sight(&self, from: &Self::State, to: &Self::State) -> bool;
+
+    /// Cost of following the straight line between two states in sight of each other
+    ///
+    /// Between neighbouring states, this must be the cost of the control between them.
+    fn distance(&self, from: &Self::State, to: &Self::State) -> Self::Cost;
+}
+
+/// Generate the controls to try from a state
+///
+/// The sampler is asked for controls every time the optimizer expands a state, so the
+/// controls it offers can depend on the state, for example only offering an action when the
+/// entity can afford it, or on a random number generator.  Samplers which generate their
+/// controls keep them in a buffer they own and return a view of it.
+///
+/// See the [`sampler`] module for samplers which combine other samplers.
+///
+/// [`sampler`]: sampler/index.html
 pub trait Sampler<M>
 where
     M: Model,
 {
+    /// The controls to apply to the `current` state
     fn sample(&mut self, model: &M, current: &M::State) -> &[M::Control];
+
+    /// Sample the controls of this sampler followed by the controls of `other`
+    fn chain<T>(self, other: T) -> sampler::Chain<M, Self, T>
+    where
+        Self: Sized,
+        T: Sampler<M>,
+    {
+        sampler::Chain::new(self, other)
+    }
+
+    /// Only sample the controls which satisfy the `predicate`
+    fn filter<F>(self, predicate: F) -> sampler::Filter<M, Self, F>
+    where
+        Self: Sized,
+        F: FnMut(&M, &M::State, &M::Control) -> bool,
+    {
+        sampler::Filter::new(self, predicate)
+    }
+
+    /// Sample from this sampler with probability `ratio`, otherwise sample from `other`
+    fn mix<T, R>(self, other: T, ratio: f64, rng: R) -> sampler::Mix<M, Self, T, R>
+    where
+        Self: Sized,
+        T: Sampler<M>,
+        R: rand::Rng,
+    {
+        sampler::Mix::new(self, other, ratio, rng)
+    }
+}
+
+impl<M, S> Sampler<M> for &mut S
+where
+    M: Model,
+    S: Sampler<M> + ?Sized,
+{
+    #[inline]
+    fn sample(&mut self, model: &M, current: &M::State) -> &[M::Control] {
+        (**self).sample(model, current)
+    }
 }
 
 /// The result of optimization: a trajectory from the start to goal
 ///
 /// A trajectory which carries the cost of its execution, and all of the steps as pairs of
 /// states and controls, who's types are determined by the Model.
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, PartialEq)]
 pub struct Trajectory<M>
 where
     M: Model,
 {
     pub cost: M::Cost,
     pub trajectory: Vec<(M::State, M::Control)>,
+    /// Index of the goal the trajectory reaches, when planning to one of a set of goals
+    pub goal: usize,
 }
 
 impl<M> Default for Trajectory<M>
@@ -198,15 +509,146 @@ where
     M: Model,
 {
     fn default() -> Self {
-        Trajectory { cost: Default::default(), trajectory: Vec::new() }
+        Trajectory { cost: Default::default(), trajectory: Vec::new(), goal: 0 }
+    }
+}
+
+// Implemented by hand, so that trajectories can be cloned without cloning the model
+impl<M> Clone for Trajectory<M>
+where
+    M: Model,
+{
+    fn clone(&self) -> Self {
+        Trajectory {
+            cost: self.cost.clone(),
+            trajectory: self.trajectory.clone(),
+            goal: self.goal,
+        }
+    }
+}
+
+/// What a search did before it failed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diagnostics {
+    /// Number of states expanded
+    pub expansions: usize,
+    /// Number of distinct positions discovered
+    ///
+    /// When the goal is unreachable, these are all the positions reachable from the start.
+    pub closed: usize,
+    /// The bounding box of the tiles of the expanded states, for states on a map
+    pub region: Option<Rect>,
+}
+
+impl Diagnostics {
+    /// Grow the region to include a tile
+    pub fn include(&mut self, tile: &Position) {
+        self.region = Some(match self.region.take() {
+            None => Rect::new(tile.clone(), 1, 1),
+            Some(rect) => {
+                let left = rect.pos.x.min(tile.x);
+                let top = rect.pos.y.min(tile.y);
+                let right = (rect.pos.x + rect.w).max(tile.x + 1);
+                let bottom = (rect.pos.y + rect.h).max(tile.y + 1);
+                Rect::new((left, top), right - left, bottom - top)
+            }
+        });
     }
 }
 
 /// Errors that result from
 #[derive(Debug, Clone, PartialEq)]
 pub enum PathFindingErr {
-    Unreachable,
+    /// Every state reachable from the start was expanded without reaching a goal
+    Unreachable(Diagnostics),
+    /// The search gave up after expanding this many states
     IterationLimit(usize),
+    /// The model rejected the start state
+    StartInvalid(Diagnostics),
+    /// Every goal is on a tile which cannot be entered
+    GoalBlocked(Diagnostics),
+    /// Every goal lies outside of the map
+    GoalOutOfBounds(Diagnostics),
+    /// The search was cancelled through its [`Budget`](struct.Budget.html)
+    Cancelled(Diagnostics),
+    /// The search ran out of the time allowed by its [`Budget`](struct.Budget.html)
+    Timeout(Diagnostics),
+}
+
+impl PathFindingErr {
+    /// The diagnostics of the failed search, if the error carries any
+    pub fn diagnostics(&self) -> Option<&Diagnostics> {
+        use PathFindingErr::*;
+
+        match self {
+            Unreachable(diagnostics)
+            | StartInvalid(diagnostics)
+            | GoalBlocked(diagnostics)
+            | GoalOutOfBounds(diagnostics)
+            | Cancelled(diagnostics)
+            | Timeout(diagnostics) => Some(diagnostics),
+            IterationLimit(_) => None,
+        }
+    }
+}
+
+/// Check the problem with the model before searching for the nearest of `goals`
+///
+/// Fails when the start is invalid, or with the error of the first goal when every goal is.
+/// A goal rejected as [`Unreachable`] is searched anyway when the budget asks for best
+/// effort, so that the search can return a partial trajectory toward it.
+///
+/// [`Unreachable`]: enum.PathFindingErr.html#variant.Unreachable
+pub(crate) fn validate<M>(
+    model: &M,
+    start: &M::State,
+    goals: &[M::Goal],
+    budget: &Budget<M>,
+) -> Result<(), PathFindingErr>
+where
+    M: Model,
+{
+    let mut first = None;
+    for goal in goals {
+        match model.validate(start, goal) {
+            Ok(()) => return Ok(()),
+            Err(err @ PathFindingErr::StartInvalid(_)) => return Err(err),
+            Err(PathFindingErr::Unreachable(_)) if budget.metric().is_some() => return Ok(()),
+            Err(err) => {
+                first.get_or_insert(err);
+            }
+        }
+    }
+
+    match first {
+        Some(err) => Err(err),
+        None => Err(PathFindingErr::Unreachable(Diagnostics::default())),
+    }
+}
+
+/// Run the hooks which end a search, unless the result leaves the search running
+///
+/// `searching` is left set only while the search is still running.
+pub(crate) fn conclude<M>(
+    model: &mut M,
+    result: PathResult<M>,
+    searching: &mut bool,
+) -> PathResult<M>
+where
+    M: Model,
+{
+    *searching = false;
+    match &result {
+        PathResult::Intermediate(_) => {
+            *searching = true;
+            return result;
+        }
+        PathResult::Final(trajectory) => model.on_solution(trajectory),
+        _ => {}
+    }
+
+    model.finish();
+    result
 }
 
 #[derive(Debug, Clone)]
@@ -216,6 +658,9 @@ where
 {
     Final(Trajectory<M>),
     Intermediate(Trajectory<M>),
+    /// A trajectory toward the goal which does not reach it, returned on failure when the
+    /// optimizer's [`Budget`](struct.Budget.html) asks for best effort
+    Partial(Trajectory<M>),
     Err(PathFindingErr),
 }
 
@@ -226,29 +671,78 @@ where
     M::Cost: Ord + Eq + Default,
     S: Sampler<M>,
 {
+    /// Abandon the search in progress, so that the next call starts a new one
+    ///
+    /// A search runs until it returns anything other than an [`Intermediate`] trajectory, and
+    /// the call after that starts a new search by itself.  The optimizer keeps its memory for
+    /// the following searches either way.
+    ///
+    /// [`Intermediate`]: enum.PathResult.html#variant.Intermediate
+    fn clear(&mut self);
+
     /// Trajectory to the head node in the planning queue, not to the optimal solution
     fn next_trajectory(
         &mut self,
         model: &mut M,
         start: &M::State,
-        goal: &M::State,
+        goal: &M::Goal,
         sampler: &mut S,
     ) -> PathResult<M>;
 
     /// Calcualte an optimal trajectory with SBMPO
     ///
     /// Using the types defiend by the provided model, we find the optimial trajectory which
-    /// connects the start and goal states by sampling controls using the states.
+    /// connects the start state and goal by sampling controls using the states.
     fn optimize(
         &mut self,
         model: &mut M,
         start: &M::State,
-        goal: &M::State,
+        goal: &M::Goal,
+        sampler: &mut S,
+    ) -> PathResult<M> {
+        self.optimize_nearest(model, start, std::slice::from_ref(goal), sampler)
+    }
+
+    /// Calculate an optimal trajectory to the nearest of a set of goals in a single search
+    ///
+    /// The search converges on the first goal reached, which is the cheapest to reach of the
+    /// set, and reports its index in `goals` as [`Trajectory::goal`].  Heuristic optimizers
+    /// estimate the cost with the smallest heuristic over the whole set.
+    ///
+    /// ```
+    /// # use game_lib::actor::{Actor, TurnOptimal, WalkSampler};
+    /// # use game_lib::map::{Map, Tile};
+    /// # use game_lib::Position;
+    /// use game_lib::path::{astar::AStar, Optimizer, PathResult, Target};
+    ///
+    /// let mut map = Map::new(20, 3);
+    /// for x in 1..19 {
+    ///     map[(x, 1)] = Tile::FLOOR;
+    /// }
+    ///
+    /// let mut model = TurnOptimal::new(map);
+    /// let exits = vec![Target::At(Position::new(1, 1)), Target::At(Position::new(18, 1))];
+    /// let monster = Actor::new(12, 1, 0, 10);
+    ///
+    /// let mut planner = AStar::new();
+    /// match planner.optimize_nearest(&mut model, &monster, &exits, &mut WalkSampler::new()) {
+    ///     PathResult::Final(trajectory) => assert_eq!(trajectory.goal, 1),
+    ///     _ => panic!("both exits are reachable"),
+    /// }
+    /// ```
+    ///
+    /// [`Trajectory::goal`]: struct.Trajectory.html#structfield.goal
+    fn optimize_nearest(
+        &mut self,
+        model: &mut M,
+        start: &M::State,
+        goals: &[M::Goal],
         sampler: &mut S,
     ) -> PathResult<M>;
 }
 
 use self::astar::{AStar, OptimalAStar};
+use self::beam::Beam;
 use self::dijkstra::Dijkstra;
 
 pub enum Algorithm<M>
@@ -259,6 +753,7 @@ where
     AStar(AStar<M>),
     Dijkstra(Dijkstra<M>),
     OptimalAStar(OptimalAStar<M>),
+    Beam(Beam<M>),
 }
 
 impl<M, S> Optimizer<M, S> for Algorithm<M>
@@ -267,31 +762,37 @@ where
     M::Cost: radix_heap::Radix + Copy,
     S: Sampler<M>,
 {
+    fn clear(&mut self) {
+        Algorithm::clear(self)
+    }
+
     fn next_trajectory(
         &mut self,
         model: &mut M,
         start: &M::State,
-        goal: &M::State,
+        goal: &M::Goal,
         sampler: &mut S,
     ) -> PathResult<M> {
         match self {
             Algorithm::AStar(o) => o.next_trajectory(model, start, goal, sampler),
             Algorithm::OptimalAStar(o) => o.next_trajectory(model, start, goal, sampler),
             Algorithm::Dijkstra(o) => o.next_trajectory(model, start, goal, sampler),
+            Algorithm::Beam(o) => o.next_trajectory(model, start, goal, sampler),
         }
     }
 
-    fn optimize(
+    fn optimize_nearest(
         &mut self,
         model: &mut M,
         start: &M::State,
-        goal: &M::State,
+        goals: &[M::Goal],
         sampler: &mut S,
     ) -> PathResult<M> {
         match self {
-            Algorithm::AStar(o) => o.optimize(model, start, goal, sampler),
-            Algorithm::OptimalAStar(o) => o.optimize(model, start, goal, sampler),
-            Algorithm::Dijkstra(o) => o.optimize(model, start, goal, sampler),
+            Algorithm::AStar(o) => o.optimize_nearest(model, start, goals, sampler),
+            Algorithm::OptimalAStar(o) => o.optimize_nearest(model, start, goals, sampler),
+            Algorithm::Dijkstra(o) => o.optimize_nearest(model, start, goals, sampler),
+            Algorithm::Beam(o) => o.optimize_nearest(model, start, goals, sampler),
         }
     }
 }
@@ -317,12 +818,28 @@ where
         Algorithm::Dijkstra(Dijkstra::default())
     }
 
+    /// Beam search which keeps `width` states per layer
+    pub fn beam(width: usize) -> Self {
+        Algorithm::Beam(Beam::new(width))
+    }
+
     pub fn toggle(&mut self) {
         match self {
             Algorithm::AStar(_) => *self = Self::dijkstra(),
             // hack: Skip optimal A* in the rotation
             Algorithm::OptimalAStar(_) => *self = Self::dijkstra(),
-            Algorithm::Dijkstra(_) => *self = Self::astar(),
+            Algorithm::Dijkstra(_) => *self = Self::Beam(Beam::default()),
+            Algorithm::Beam(_) => *self = Self::astar(),
+        }
+    }
+
+    /// Limit the work of the following searches, and what they return when giving up
+    pub fn set_budget(&mut self, budget: Budget<M>) {
+        match self {
+            Algorithm::AStar(o) => o.set_budget(budget),
+            Algorithm::OptimalAStar(o) => o.set_budget(budget),
+            Algorithm::Dijkstra(o) => o.set_budget(budget),
+            Algorithm::Beam(o) => o.set_budget(budget),
         }
     }
 
@@ -331,6 +848,22 @@ where
             Algorithm::AStar(o) => o.clear(),
             Algorithm::OptimalAStar(o) => o.clear(),
             Algorithm::Dijkstra(o) => o.clear(),
+            Algorithm::Beam(o) => o.clear(),
+        }
+    }
+
+    /// Keep every state which no other state at its grid position dominates
+    ///
+    /// Beam search keeps a single state per grid position regardless.
+    pub fn use_dominance(&mut self)
+    where
+        M::State: Dominance,
+    {
+        match self {
+            Algorithm::AStar(o) => o.use_dominance(),
+            Algorithm::OptimalAStar(o) => o.use_dominance(),
+            Algorithm::Dijkstra(o) => o.use_dominance(),
+            Algorithm::Beam(_) => {}
         }
     }
 
@@ -341,6 +874,7 @@ where
             Algorithm::AStar(o) => Box::new(o.inspect_queue()),
             Algorithm::OptimalAStar(o) => Box::new(o.inspect_queue()),
             Algorithm::Dijkstra(o) => Box::new(o.inspect_queue()),
+            Algorithm::Beam(o) => Box::new(o.inspect_queue()),
         }
     }
 
@@ -351,6 +885,7 @@ where
             Algorithm::AStar(o) => Box::new(o.inspect_discovered()),
             Algorithm::OptimalAStar(o) => Box::new(o.inspect_discovered()),
             Algorithm::Dijkstra(o) => Box::new(o.inspect_discovered()),
+            Algorithm::Beam(o) => Box::new(o.inspect_discovered()),
         }
     }
 }
@@ -0,0 +1,316 @@
+use super::{plan_one, Pos};
+use crate::path::spacetime::{Reservations, Timed};
+use crate::path::{
+    Diagnostics, HeuristicModel, Model, PathFindingErr, PathResult, Sampler, State, Trajectory,
+};
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::hash::Hash;
+
+/// Two entities which collide when following their trajectories
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Conflict<P> {
+    /// Both entities are at `position` on `turn`
+    Vertex { agents: (usize, usize), position: P, turn: usize },
+    /// The first entity moves from `from` to `to` between `turn` and the next, while the
+    /// second moves the other way
+    Swap { agents: (usize, usize), from: P, to: P, turn: usize },
+}
+
+impl<P> Conflict<P>
+where
+    P: Eq + Clone,
+{
+    /// Find the earliest conflict between trajectories which start on `turn`
+    ///
+    /// Entities stay at the end of their trajectory once they have followed it.
+    pub fn find<M>(trajectories: &[Trajectory<M>], turn: usize) -> Option<Self>
+    where
+        M: Model,
+        M::State: State<Position = P>,
+    {
+        let paths: Vec<Vec<P>> = trajectories
+            .iter()
+            .map(|t| t.trajectory.iter().map(|(state, _)| state.grid_position()).collect())
+            .collect();
+        let at = |path: &Vec<P>, step: usize| path.get(step).or_else(|| path.last()).cloned();
+        let steps = paths.iter().map(Vec::len).max().unwrap_or(0);
+
+        for step in 0..steps {
+            for a in 0..paths.len() {
+                for b in a + 1..paths.len() {
+                    let (a_now, b_now) = (at(&paths[a], step), at(&paths[b], step));
+                    if a_now.is_some() && a_now == b_now {
+                        return Some(Conflict::Vertex {
+                            agents: (a, b),
+                            position: a_now?,
+                            turn: turn + step,
+                        });
+                    }
+
+                    let (a_next, b_next) = (at(&paths[a], step + 1), at(&paths[b], step + 1));
+                    if a_now != a_next && a_now == b_next && a_next == b_now {
+                        return Some(Conflict::Swap {
+                            agents: (a, b),
+                            from: a_now?,
+                            to: a_next?,
+                            turn: turn + step,
+                        });
+                    }
+                }
+            }
+        }
+
+        None
+    }
+
+    /// The entities in conflict
+    pub fn agents(&self) -> (usize, usize) {
+        match self {
+            Conflict::Vertex { agents, .. } | Conflict::Swap { agents, .. } => *agents,
+        }
+    }
+
+    /// Forbid the first or second entity from its part of the conflict
+    fn forbid(&self, first: bool, constraints: &mut Reservations<P>)
+    where
+        P: Hash,
+    {
+        match self {
+            Conflict::Vertex { position, turn, .. } => {
+                constraints.reserve(position.clone(), *turn)
+            }
+            Conflict::Swap { from, to, turn, .. } => {
+                // A move is forbidden by reserving the move in the opposite direction
+                if first {
+                    constraints.reserve_edge(to.clone(), from.clone(), *turn)
+                } else {
+                    constraints.reserve_edge(from.clone(), to.clone(), *turn)
+                }
+            }
+        }
+    }
+}
+
+/// Conflict-Based Search, which finds the plan with the minimum sum of costs for all entities
+///
+/// Every entity is first planned alone.  Whenever two trajectories conflict, the search
+/// branches in two: one branch forbids the first entity from the conflict, and the other
+/// forbids the second, and only the forbidden entity is planned again around its
+/// constraints.  The branches are explored in order of their sum of costs, so the first
+/// plan without conflicts has the lowest sum of costs among the plans within the horizon.
+///
+/// That only holds when every entity is planned optimally, which takes a heuristic that
+/// never over-estimates the cost to the goal.  With [`TurnOptimal`], use
+/// [`Heuristic::Diagonal`] or the [`Heuristic::Moves`] of the sampler: the default
+/// [`Heuristic::Manhattan`] over-estimates diagonal steps, and the plan may then cost more.
+///
+/// The search can grow exponentially with the number of conflicts, so it is meant for a
+/// handful of entities which have to coordinate.  Once it has explored more branches than
+/// its limit, it fails with [`PathFindingErr::IterationLimit`] rather than returning the
+/// best plan so far, even if that plan has no conflict left to resolve.
+///
+/// [`TurnOptimal`]: ../../actor/struct.TurnOptimal.html
+/// [`Heuristic::Diagonal`]: ../../actor/enum.Heuristic.html#variant.Diagonal
+/// [`Heuristic::Moves`]: ../../actor/enum.Heuristic.html#variant.Moves
+/// [`Heuristic::Manhattan`]: ../../actor/enum.Heuristic.html#variant.Manhattan
+/// [`PathFindingErr::IterationLimit`]: ../enum.PathFindingErr.html#variant.IterationLimit
+///
+/// ```
+/// # use game_lib::map::{Map, Tile};
+/// # use game_lib::Position;
+/// use game_lib::actor::{Actor, Heuristic, TurnOptimal, WalkSampler};
+/// use game_lib::path::multi::{Conflict, ConflictBased};
+/// use game_lib::path::Target;
+///
+/// // A corridor with a pocket to step aside into
+/// let mut map = Map::new(7, 4);
+/// for x in 1..6 {
+///     map[(x, 1)] = Tile::FLOOR;
+/// }
+/// map[(3, 2)] = Tile::FLOOR;
+///
+/// let agents = vec![
+///     (Actor::new(1, 1, 0, 0), Target::At(Position::new(5, 1))),
+///     (Actor::new(5, 1, 0, 0), Target::At(Position::new(1, 1))),
+/// ];
+///
+/// let mut planner = ConflictBased::new(32);
+/// let mut model = TurnOptimal::new(map);
+/// model.set_heuristic(Heuristic::Diagonal);
+/// let plans = planner.plan(&mut model, 0, &agents, &mut WalkSampler::with_wait()).unwrap();
+///
+/// assert_eq!(Conflict::find(&plans, 0), None);
+/// // One actor walks straight through while the other steps through the pocket
+/// assert_eq!(plans.iter().map(|plan| plan.cost).sum::<usize>(), 8 + 10);
+/// ```
+#[derive(Debug)]
+pub struct ConflictBased<M>
+where
+    M: Model,
+{
+    horizon: usize,
+    limit: usize,
+    obstacles: Reservations<Pos<M>>,
+}
+
+/// A branch of the search, with the constraints of every entity
+struct Branch<M>
+where
+    M: Model,
+{
+    constraints: Vec<Reservations<Pos<M>>>,
+    trajectories: Vec<Trajectory<M>>,
+}
+
+impl<M> ConflictBased<M>
+where
+    M: HeuristicModel,
+    M::Cost: radix_heap::Radix + Copy,
+    Pos<M>: Clone,
+{
+    /// Plan paths of at most `horizon` turns, exploring at most 1024 branches
+    pub fn new(horizon: usize) -> Self {
+        ConflictBased { horizon, limit: 1024, obstacles: Reservations::new() }
+    }
+
+    /// Give up after exploring `limit` branches
+    ///
+    /// A search which reaches the limit fails with [`IterationLimit`], so a lower limit
+    /// fails more often but never returns a costlier plan.
+    ///
+    /// [`IterationLimit`]: ../enum.PathFindingErr.html#variant.IterationLimit
+    pub fn with_limit(mut self, limit: usize) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    /// Reservations which every entity avoids, such as the patrol of a guard
+    pub fn obstacles(&mut self) -> &mut Reservations<Pos<M>> {
+        &mut self.obstacles
+    }
+
+    /// Plan every entity from its state on `turn` to its goal without any conflict
+    ///
+    /// The trajectories are returned in the order of the entities.
+    ///
+    /// ```
+    /// # use game_lib::map::{Map, Tile};
+    /// # use game_lib::Position;
+    /// use game_lib::actor::{Actor, Heuristic, TurnOptimal, WalkSampler};
+    /// use game_lib::path::astar::AStar;
+    /// use game_lib::path::multi::{Conflict, ConflictBased};
+    /// use game_lib::path::{Optimizer, PathFindingErr, PathResult, Target};
+    ///
+    /// // Two corridors crossing in the middle
+    /// let mut map = Map::new(7, 7);
+    /// for i in 1..6 {
+    ///     map[(i, 3)] = Tile::FLOOR;
+    ///     map[(3, i)] = Tile::FLOOR;
+    /// }
+    ///
+    /// let agents = vec![
+    ///     (Actor::new(1, 3, 0, 0), Target::At(Position::new(5, 3))),
+    ///     (Actor::new(3, 1, 0, 0), Target::At(Position::new(3, 5))),
+    /// ];
+    /// let mut model = TurnOptimal::new(map);
+    /// model.set_heuristic(Heuristic::Diagonal);
+    ///
+    /// // Planned alone, both entities reach the crossing on the second turn
+    /// let alone: Vec<_> = agents
+    ///     .iter()
+    ///     .map(|(start, goal)| {
+    ///         match AStar::new().optimize(&mut model, start, goal, &mut WalkSampler::new()) {
+    ///             PathResult::Final(trajectory) => trajectory,
+    ///             _ => panic!("the corridors are open"),
+    ///         }
+    ///     })
+    ///     .collect();
+    /// let conflict = Conflict::Vertex { agents: (0, 1), position: Position::new(3, 3), turn: 2 };
+    /// assert_eq!(Conflict::find(&alone, 0), Some(conflict));
+    ///
+    /// // Resolving the conflict makes one of them wait a turn
+    /// let mut sampler = WalkSampler::with_wait();
+    /// let plans = ConflictBased::new(16).plan(&mut model, 0, &agents, &mut sampler).unwrap();
+    /// assert_eq!(Conflict::find(&plans, 0), None);
+    /// assert_eq!(plans.iter().map(|plan| plan.cost).sum::<usize>(), 8 + 8 + 2);
+    ///
+    /// // Which takes more than the first branch
+    /// match ConflictBased::new(16).with_limit(1).plan(&mut model, 0, &agents, &mut sampler) {
+    ///     Err(PathFindingErr::IterationLimit(_)) => {}
+    ///     _ => panic!("the conflict needs a branch"),
+    /// }
+    /// ```
+    pub fn plan<S>(
+        &mut self,
+        model: &mut M,
+        turn: usize,
+        agents: &[(M::State, M::Goal)],
+        sampler: &mut S,
+    ) -> Result<Vec<Trajectory<M>>, PathFindingErr>
+    where
+        S: Sampler<M>,
+    {
+        let horizon = turn + self.horizon;
+        let mut replan = |model: &mut M, constraints: &Reservations<Pos<M>>, agent: usize| {
+            let (start, goal) = &agents[agent];
+            let start = Timed::new(start.clone(), turn);
+            match plan_one(model, constraints, horizon, false, start, goal, &mut *sampler) {
+                PathResult::Final(trajectory) => Ok(trajectory),
+                PathResult::Intermediate(_) | PathResult::Partial(_) => {
+                    Err(PathFindingErr::Unreachable(Diagnostics::default()))
+                }
+                PathResult::Err(err) => Err(err),
+            }
+        };
+
+        let constraints = vec![self.obstacles.clone(); agents.len()];
+        let trajectories = (0..agents.len())
+            .map(|agent| replan(model, &constraints[agent], agent))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut branches = vec![Branch::<M> { constraints, trajectories }];
+        let mut queue = BinaryHeap::new();
+        queue.push(Reverse((sum_of_costs(&branches[0].trajectories), 0)));
+
+        while let Some(Reverse((_, index))) = queue.pop() {
+            if branches.len() > self.limit {
+                return Err(PathFindingErr::IterationLimit(branches.len()));
+            }
+
+            let conflict = match Conflict::find(&branches[index].trajectories, turn) {
+                Some(conflict) => conflict,
+                None => return Ok(branches.swap_remove(index).trajectories),
+            };
+
+            let (a, b) = conflict.agents();
+            for &(agent, first) in [(a, true), (b, false)].iter() {
+                let mut constraints = branches[index].constraints.clone();
+                conflict.forbid(first, &mut constraints[agent]);
+
+                if let Ok(trajectory) = replan(model, &constraints[agent], agent) {
+                    let mut trajectories = branches[index].trajectories.clone();
+                    trajectories[agent] = trajectory;
+
+                    queue.push(Reverse((sum_of_costs(&trajectories), branches.len())));
+                    branches.push(Branch { constraints, trajectories });
+                }
+            }
+        }
+
+        // Every branch has an entity which cannot avoid its constraints
+        Err(PathFindingErr::Unreachable(Diagnostics {
+            expansions: branches.len(),
+            ..Default::default()
+        }))
+    }
+}
+
+fn sum_of_costs<M>(trajectories: &[Trajectory<M>]) -> M::Cost
+where
+    M: Model,
+    M::Cost: Copy,
+{
+    trajectories.iter().fold(Default::default(), |sum, t| sum + t.cost)
+}
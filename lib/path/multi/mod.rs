@@ -0,0 +1,240 @@
+//! Planning for many entities at once, without collisions between them
+//!
+//! Entities which plan alone happily walk into the same tile on the same turn.  The planners
+//! in this module plan every entity through time with a [`SpaceTime`] model, and return one
+//! [`Trajectory`] per entity where the n-th step is taken on the n-th turn.
+//!
+//! [`Cooperative`] plans the entities one after the other, each one reserving its path in a
+//! shared [`Reservations`] table which the following entities avoid.  [`ConflictBased`]
+//! searches for the plan with the minimum sum of costs instead, which is slower, but also
+//! solves the puzzles where no order of priority works.
+//!
+//! ```
+//! # use game_lib::map::{Map, Tile};
+//! # use game_lib::Position;
+//! use game_lib::actor::{Actor, TurnOptimal, WalkSampler};
+//! use game_lib::path::multi::Cooperative;
+//! use game_lib::path::{PathResult, Target};
+//!
+//! // A corridor with a pocket to step aside into
+//! let mut map = Map::new(7, 4);
+//! for x in 1..6 {
+//!     map[(x, 1)] = Tile::FLOOR;
+//! }
+//! map[(3, 2)] = Tile::FLOOR;
+//!
+//! let agents = vec![
+//!     (Actor::new(1, 1, 0, 0), Target::At(Position::new(5, 1))),
+//!     (Actor::new(5, 1, 0, 0), Target::At(Position::new(1, 1))),
+//! ];
+//!
+//! let mut planner = Cooperative::new(32);
+//! let mut model = TurnOptimal::new(map);
+//! let plans = planner.plan(&mut model, 0, &agents, &mut WalkSampler::with_wait());
+//!
+//! let paths: Vec<Vec<Position>> = plans
+//!     .into_iter()
+//!     .map(|plan| match plan {
+//!         PathResult::Final(t) => t.trajectory.into_iter().map(|(s, _)| s.pos).collect(),
+//!         _ => panic!("both actors can pass each other"),
+//!     })
+//!     .collect();
+//!
+//! for turn in 0..paths[0].len().max(paths[1].len()) {
+//!     let at = |path: &Vec<Position>| path[turn.min(path.len() - 1)].clone();
+//!     assert_ne!(at(&paths[0]), at(&paths[1]));
+//! }
+//! ```
+//!
+//! [`SpaceTime`]: ../spacetime/struct.SpaceTime.html
+//! [`Trajectory`]: ../struct.Trajectory.html
+//! [`Cooperative`]: struct.Cooperative.html
+//! [`ConflictBased`]: struct.ConflictBased.html
+//! [`Reservations`]: ../spacetime/struct.Reservations.html
+
+use super::astar::AStar;
+use super::spacetime::{Reservations, SpaceTime, Timed, TimedSampler};
+use super::{HeuristicModel, Model, Optimizer, PathResult, Sampler, State, Trajectory};
+
+mod cbs;
+
+pub use self::cbs::{Conflict, ConflictBased};
+
+/// Grid position of the states of a model
+type Pos<M> = <<M as Model>::State as State>::Position;
+
+/// Cooperative A*, which plans entities in order of priority around each other's plans
+///
+/// Each entity is planned with A* through time, avoiding the positions and moves reserved
+/// by the entities before it, and then reserves its own path.  An entity which reaches its
+/// goal stays there, so its goal is reserved from then on.  An entity which cannot be
+/// planned for stays where it is.
+///
+/// With a window, known as Windowed Hierarchical Cooperative A* (WHCA*), only the first few
+/// turns are planned and reserved.  Plans which do not reach their goal within the window
+/// end at the state which looks the closest to it, and should be planned again before the
+/// window runs out.  The table is cleared at the start of every call to [`plan`], except for
+/// the reservations made through [`obstacles`].
+///
+/// [`plan`]: #method.plan
+/// [`obstacles`]: #method.obstacles
+#[derive(Debug)]
+pub struct Cooperative<M>
+where
+    M: Model,
+{
+    horizon: usize,
+    window: Option<usize>,
+    obstacles: Reservations<Pos<M>>,
+    reservations: Reservations<Pos<M>>,
+}
+
+impl<M> Cooperative<M>
+where
+    M: HeuristicModel,
+    M::Cost: radix_heap::Radix + Copy,
+    Pos<M>: Clone,
+{
+    /// Plan complete paths of at most `horizon` turns
+    pub fn new(horizon: usize) -> Self {
+        Cooperative {
+            horizon,
+            window: None,
+            obstacles: Reservations::new(),
+            reservations: Reservations::new(),
+        }
+    }
+
+    /// Only plan and reserve the next `window` turns of every entity
+    pub fn windowed(window: usize) -> Self {
+        Cooperative { window: Some(window), ..Self::new(window) }
+    }
+
+    /// Reservations which are kept between calls to plan, such as the patrol of a guard
+    pub fn obstacles(&mut self) -> &mut Reservations<Pos<M>> {
+        &mut self.obstacles
+    }
+
+    /// The reservations made by the last call to plan, including the obstacles
+    pub fn reservations(&self) -> &Reservations<Pos<M>> {
+        &self.reservations
+    }
+
+    /// Plan every entity from its state on `turn` to its goal, in order of priority
+    ///
+    /// The sampler should offer a way to wait, otherwise entities can only avoid each
+    /// other by taking detours.
+    pub fn plan<S>(
+        &mut self,
+        model: &mut M,
+        turn: usize,
+        agents: &[(M::State, M::Goal)],
+        sampler: &mut S,
+    ) -> Vec<PathResult<M>>
+    where
+        S: Sampler<M>,
+    {
+        self.reservations = self.obstacles.clone();
+
+        agents
+            .iter()
+            .map(|(start, goal)| {
+                let result = plan_one(
+                    model,
+                    &self.reservations,
+                    turn + self.horizon,
+                    self.window.is_some(),
+                    Timed::new(start.clone(), turn),
+                    goal,
+                    &mut *sampler,
+                );
+
+                match &result {
+                    PathResult::Final(trajectory) => {
+                        let parks = trajectory
+                            .trajectory
+                            .last()
+                            .map(|(last, _)| model.converge(last, goal))
+                            .unwrap_or(false);
+                        self.reserve(trajectory, turn, parks);
+                    }
+                    PathResult::Intermediate(_) | PathResult::Partial(_) => {}
+                    PathResult::Err(_) => {
+                        self.reservations.reserve_from(start.grid_position(), turn);
+                    }
+                }
+
+                result
+            })
+            .collect()
+    }
+
+    fn reserve(&mut self, trajectory: &Trajectory<M>, turn: usize, parks: bool) {
+        let positions = trajectory.trajectory.iter().map(|(state, _)| state.grid_position());
+        let mut previous: Option<Pos<M>> = None;
+        let mut last_turn = turn;
+
+        for (turn, pos) in (turn..).zip(positions) {
+            if let Some(previous) = previous.take() {
+                self.reservations.reserve_edge(previous, pos.clone(), turn - 1);
+            }
+            self.reservations.reserve(pos.clone(), turn);
+            previous = Some(pos);
+            last_turn = turn;
+        }
+
+        if let Some(last) = previous.filter(|_| parks) {
+            self.reservations.reserve_from(last, last_turn);
+        }
+    }
+}
+
+/// Plan a single entity through time with A*, around a table of reservations
+fn plan_one<M, S>(
+    model: &mut M,
+    reservations: &Reservations<Pos<M>>,
+    horizon: usize,
+    windowed: bool,
+    start: Timed<M::State>,
+    goal: &M::Goal,
+    sampler: &mut S,
+) -> PathResult<M>
+where
+    M: HeuristicModel,
+    M::Cost: radix_heap::Radix + Copy,
+    Pos<M>: Clone,
+    S: Sampler<M>,
+{
+    let mut space_time = SpaceTime::new(model, reservations, horizon);
+    if windowed {
+        space_time = space_time.windowed();
+    }
+
+    let mut sampler = TimedSampler::new(sampler);
+    match AStar::new().optimize(&mut space_time, &start, goal, &mut sampler) {
+        PathResult::Final(trajectory) => PathResult::Final(untimed(trajectory)),
+        PathResult::Intermediate(trajectory) => PathResult::Intermediate(untimed(trajectory)),
+        PathResult::Partial(trajectory) => PathResult::Partial(untimed(trajectory)),
+        PathResult::Err(err) => PathResult::Err(err),
+    }
+}
+
+/// Drop the turns from a trajectory planned through time
+///
+/// The turns are implied by the position in the trajectory, starting from the turn the plan
+/// was made on.
+pub fn untimed<M>(trajectory: Trajectory<SpaceTime<'_, M>>) -> Trajectory<M>
+where
+    M: Model,
+    Pos<M>: Clone,
+{
+    Trajectory {
+        cost: trajectory.cost,
+        trajectory: trajectory
+            .trajectory
+            .into_iter()
+            .map(|(timed, control)| (timed.state, control))
+            .collect(),
+        goal: trajectory.goal,
+    }
+}
@@ -0,0 +1,157 @@
+//! Reusing optimizers from one query to the next
+//!
+//! Optimizers keep their queues and tables between searches, and clearing them keeps the
+//! memory for the next search.  A [`PlannerPool`] holds optimizers which are ready to plan,
+//! so that once the optimizers grew to the size of the searches, planning every turn for many
+//! entities only allocates the trajectories returned.
+//!
+//! ```
+//! # use game_lib::map::{Map, Tile};
+//! # use game_lib::Position;
+//! use game_lib::actor::{Actor, Heuristic, TurnOptimal, WalkSampler};
+//! use game_lib::path::pool::PlannerPool;
+//! use game_lib::path::{Optimizer, PathResult, Target};
+//!
+//! let mut map = Map::new(20, 3);
+//! for x in 1..19 {
+//!     map[(x, 1)] = Tile::FLOOR;
+//! }
+//!
+//! let mut model = TurnOptimal::new(map);
+//! model.set_heuristic(Heuristic::Diagonal);
+//! let pool = PlannerPool::astar(2, 256);
+//!
+//! let goal = Target::At(Position::new(10, 1));
+//! for x in 1..19 {
+//!     let mut planner = pool.get();
+//!     let monster = Actor::new(x, 1, 0, 0);
+//!     let distance = (i64::from(x) - 10).abs() as usize;
+//!     match planner.optimize(&mut model, &monster, &goal, &mut WalkSampler::new()) {
+//!         PathResult::Final(trajectory) => assert_eq!(trajectory.cost, 2 * distance),
+//!         _ => panic!("the corridor is open"),
+//!     }
+//! }
+//!
+//! // The planner was cleared and returned once each entity was done with it
+//! assert_eq!(pool.idle(), 2);
+//! ```
+//!
+//! [`PlannerPool`]: struct.PlannerPool.html
+
+use super::astar::AStar;
+use super::dijkstra::Dijkstra;
+use super::{HeuristicModel, Model};
+
+use radix_heap::Radix;
+
+use std::fmt::{self, Debug, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::sync::Mutex;
+
+/// A set of optimizers which are handed out to plan, and cleared once returned
+pub struct PlannerPool<O> {
+    idle: Mutex<Vec<O>>,
+    create: Box<dyn Fn() -> O + Send + Sync>,
+    clear: fn(&mut O),
+}
+
+impl<O> PlannerPool<O> {
+    /// Create `count` optimizers up front, which are cleared with `clear` when returned
+    ///
+    /// More optimizers are created when all of them are in use, and kept once returned.
+    pub fn new<F>(count: usize, create: F, clear: fn(&mut O)) -> Self
+    where
+        F: Fn() -> O + Send + Sync + 'static,
+    {
+        let idle = (0..count).map(|_| create()).collect();
+        PlannerPool { idle: Mutex::new(idle), create: Box::new(create), clear }
+    }
+
+    /// Take an idle optimizer, or create one when none is idle
+    pub fn get(&self) -> Planner<'_, O> {
+        let planner = self.lock().pop().unwrap_or_else(|| (self.create)());
+        Planner { planner: Some(planner), pool: self }
+    }
+
+    /// The number of optimizers waiting to be handed out
+    pub fn idle(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<O>> {
+        // The list of idle optimizers is never left half updated
+        self.idle.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl<M> PlannerPool<AStar<M>>
+where
+    M: HeuristicModel + 'static,
+    M::Cost: Copy,
+{
+    /// Optimizers using [`AStar`], each with room for `states` states
+    ///
+    /// [`AStar`]: ../astar/struct.AStar.html
+    pub fn astar(count: usize, states: usize) -> Self {
+        PlannerPool::new(count, move || AStar::with_capacity(states), AStar::clear)
+    }
+}
+
+impl<M> PlannerPool<Dijkstra<M>>
+where
+    M: Model + 'static,
+    M::Cost: Radix + Copy,
+{
+    /// Optimizers using [`Dijkstra`], each with room for `states` states
+    ///
+    /// [`Dijkstra`]: ../dijkstra/struct.Dijkstra.html
+    pub fn dijkstra(count: usize, states: usize) -> Self {
+        PlannerPool::new(count, move || Dijkstra::with_capacity(states), Dijkstra::clear)
+    }
+}
+
+impl<O> Debug for PlannerPool<O> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("PlannerPool").field("idle", &self.idle()).finish()
+    }
+}
+
+/// An optimizer handed out by a [`PlannerPool`], which returns to the pool when dropped
+///
+/// [`PlannerPool`]: struct.PlannerPool.html
+pub struct Planner<'a, O> {
+    planner: Option<O>,
+    pool: &'a PlannerPool<O>,
+}
+
+impl<'a, O> Deref for Planner<'a, O> {
+    type Target = O;
+
+    fn deref(&self) -> &O {
+        self.planner.as_ref().expect("the planner is only taken when dropped")
+    }
+}
+
+impl<'a, O> DerefMut for Planner<'a, O> {
+    fn deref_mut(&mut self) -> &mut O {
+        self.planner.as_mut().expect("the planner is only taken when dropped")
+    }
+}
+
+impl<'a, O> Drop for Planner<'a, O> {
+    fn drop(&mut self) {
+        if let Some(mut planner) = self.planner.take() {
+            (self.pool.clear)(&mut planner);
+            self.pool.lock().push(planner);
+        }
+    }
+}
+
+impl<'a, O> Debug for Planner<'a, O>
+where
+    O: Debug,
+{
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_tuple("Planner").field(&**self).finish()
+    }
+}
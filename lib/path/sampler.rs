@@ -0,0 +1,267 @@
+//! Samplers which generate controls from the current state, and combinators to build them
+//!
+//! SBMPO plans by sampling controls rather than by enumerating a fixed neighbourhood, so a
+//! [`Sampler`] is free to offer different controls depending on the state it expands.  The
+//! samplers in this module either generate controls themselves, like [`Random`] and
+//! [`FromFn`], or adapt other samplers, like [`Chain`], [`Filter`] and [`Mix`].
+//!
+//! ```
+//! # use game_lib::actor::{Actor, Movement, TeleportSampler, TurnOptimal};
+//! # use game_lib::map::Map;
+//! use game_lib::path::Sampler;
+//!
+//! let model = TurnOptimal::new(Map::new(10, 10));
+//! let mut teleport_only = TeleportSampler::new()
+//!     .filter(|_: &TurnOptimal, _: &Actor, m: &Movement| match m {
+//!         Movement::Teleport(_) => true,
+//!         _ => false,
+//!     });
+//!
+//! // Teleports are only sampled once the actor has enough mana to cast them
+//! assert!(teleport_only.sample(&model, &Actor::new(5, 5, 0, 10)).is_empty());
+//! assert_eq!(teleport_only.sample(&model, &Actor::new(5, 5, 10, 10)).len(), 8);
+//! ```
+//!
+//! [`Sampler`]: ../trait.Sampler.html
+//! [`Random`]: struct.Random.html
+//! [`FromFn`]: struct.FromFn.html
+//! [`Chain`]: struct.Chain.html
+//! [`Filter`]: struct.Filter.html
+//! [`Mix`]: struct.Mix.html
+
+use std::fmt::{self, Debug, Formatter};
+use std::marker::PhantomData;
+
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_xorshift::XorShiftRng;
+
+use super::{Model, Sampler};
+
+/// Sample a random subset of a set of candidate controls at every state
+///
+/// Each call to `sample` draws `count` distinct controls from the candidates, so two
+/// expansions of the same state may explore different controls.  Use [`Random::seeded`] for
+/// reproducible plans.
+///
+/// [`Random::seeded`]: #method.seeded
+pub struct Random<M, R>
+where
+    M: Model,
+    R: Rng,
+{
+    candidates: Vec<M::Control>,
+    count: usize,
+    rng: R,
+    buffer: Vec<M::Control>,
+}
+
+impl<M, R> Random<M, R>
+where
+    M: Model,
+    R: Rng,
+{
+    /// Sample `count` of the `candidates` using the provided random number generator
+    pub fn new(candidates: Vec<M::Control>, count: usize, rng: R) -> Self {
+        Random { buffer: Vec::with_capacity(count), candidates, count, rng }
+    }
+}
+
+impl<M> Random<M, XorShiftRng>
+where
+    M: Model,
+{
+    /// Sample `count` of the `candidates` from a generator seeded with `seed`
+    pub fn seeded(candidates: Vec<M::Control>, count: usize, seed: u64) -> Self {
+        Self::new(candidates, count, XorShiftRng::seed_from_u64(seed))
+    }
+}
+
+impl<M, R> Sampler<M> for Random<M, R>
+where
+    M: Model,
+    R: Rng,
+{
+    fn sample(&mut self, _: &M, _: &M::State) -> &[M::Control] {
+        self.buffer.clear();
+        let chosen = self.candidates.choose_multiple(&mut self.rng, self.count);
+        self.buffer.extend(chosen.cloned());
+        &self.buffer
+    }
+}
+
+impl<M, R> Debug for Random<M, R>
+where
+    M: Model,
+    R: Rng,
+{
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Random")
+            .field("candidates", &self.candidates)
+            .field("count", &self.count)
+            .finish()
+    }
+}
+
+/// Generate controls from the current state with a closure
+///
+/// The closure receives an empty buffer to push the sampled controls into.
+///
+/// See [`from_fn`](fn.from_fn.html).
+pub struct FromFn<M, F>
+where
+    M: Model,
+    F: FnMut(&M, &M::State, &mut Vec<M::Control>),
+{
+    generate: F,
+    buffer: Vec<M::Control>,
+}
+
+/// Create a sampler which generates controls from the current state with a closure
+pub fn from_fn<M, F>(generate: F) -> FromFn<M, F>
+where
+    M: Model,
+    F: FnMut(&M, &M::State, &mut Vec<M::Control>),
+{
+    FromFn { generate, buffer: Vec::new() }
+}
+
+impl<M, F> Sampler<M> for FromFn<M, F>
+where
+    M: Model,
+    F: FnMut(&M, &M::State, &mut Vec<M::Control>),
+{
+    fn sample(&mut self, model: &M, current: &M::State) -> &[M::Control] {
+        self.buffer.clear();
+        (self.generate)(model, current, &mut self.buffer);
+        &self.buffer
+    }
+}
+
+/// Sample the controls of one sampler followed by the controls of another
+///
+/// See [`Sampler::chain`](../trait.Sampler.html#method.chain).
+pub struct Chain<M, A, B>
+where
+    M: Model,
+{
+    first: A,
+    second: B,
+    buffer: Vec<M::Control>,
+}
+
+impl<M, A, B> Chain<M, A, B>
+where
+    M: Model,
+    A: Sampler<M>,
+    B: Sampler<M>,
+{
+    pub fn new(first: A, second: B) -> Self {
+        Chain { first, second, buffer: Vec::new() }
+    }
+}
+
+impl<M, A, B> Sampler<M> for Chain<M, A, B>
+where
+    M: Model,
+    A: Sampler<M>,
+    B: Sampler<M>,
+{
+    fn sample(&mut self, model: &M, current: &M::State) -> &[M::Control] {
+        self.buffer.clear();
+        self.buffer.extend_from_slice(self.first.sample(model, current));
+        self.buffer.extend_from_slice(self.second.sample(model, current));
+        &self.buffer
+    }
+}
+
+/// Only keep the controls of a sampler which satisfy a predicate
+///
+/// See [`Sampler::filter`](../trait.Sampler.html#method.filter).
+pub struct Filter<M, S, F>
+where
+    M: Model,
+{
+    sampler: S,
+    predicate: F,
+    buffer: Vec<M::Control>,
+}
+
+impl<M, S, F> Filter<M, S, F>
+where
+    M: Model,
+    S: Sampler<M>,
+    F: FnMut(&M, &M::State, &M::Control) -> bool,
+{
+    pub fn new(sampler: S, predicate: F) -> Self {
+        Filter { sampler, predicate, buffer: Vec::new() }
+    }
+}
+
+impl<M, S, F> Sampler<M> for Filter<M, S, F>
+where
+    M: Model,
+    S: Sampler<M>,
+    F: FnMut(&M, &M::State, &M::Control) -> bool,
+{
+    fn sample(&mut self, model: &M, current: &M::State) -> &[M::Control] {
+        self.buffer.clear();
+        let predicate = &mut self.predicate;
+        for control in self.sampler.sample(model, current) {
+            if predicate(model, current, control) {
+                self.buffer.push(control.clone());
+            }
+        }
+        &self.buffer
+    }
+}
+
+/// Randomly choose between two samplers at every state
+///
+/// The first sampler is used with probability `ratio`, otherwise the second is used.
+///
+/// See [`Sampler::mix`](../trait.Sampler.html#method.mix).
+pub struct Mix<M, A, B, R>
+where
+    M: Model,
+    R: Rng,
+{
+    first: A,
+    second: B,
+    ratio: f64,
+    rng: R,
+    model: PhantomData<M>,
+}
+
+impl<M, A, B, R> Mix<M, A, B, R>
+where
+    M: Model,
+    A: Sampler<M>,
+    B: Sampler<M>,
+    R: Rng,
+{
+    /// Mix two samplers, using the `first` with probability `ratio`
+    ///
+    /// `ratio` is clamped to the range `[0, 1]`, and a `ratio` which is not a number mixes
+    /// both samplers evenly, as if it were `0.5`.
+    pub fn new(first: A, second: B, ratio: f64, rng: R) -> Self {
+        let ratio = if ratio.is_nan() { 0.5 } else { ratio.clamp(0.0, 1.0) };
+        Mix { first, second, ratio, rng, model: PhantomData }
+    }
+}
+
+impl<M, A, B, R> Sampler<M> for Mix<M, A, B, R>
+where
+    M: Model,
+    A: Sampler<M>,
+    B: Sampler<M>,
+    R: Rng,
+{
+    fn sample(&mut self, model: &M, current: &M::State) -> &[M::Control] {
+        if self.rng.gen_bool(self.ratio) {
+            self.first.sample(model, current)
+        } else {
+            self.second.sample(model, current)
+        }
+    }
+}
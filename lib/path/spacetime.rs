@@ -0,0 +1,363 @@
+//! Planning through time as well as space, around entities which move
+//!
+//! [`SpaceTime`] extends the states of any [`Model`] with the turn they are reached on, so
+//! that a [`Reservations`] table can forbid entering a position on the turns another entity
+//! occupies it.  Since standing still becomes a useful control, the sampler should offer a
+//! way to wait, such as [`WalkSampler::with_wait`].
+//!
+//! ```
+//! # use game_lib::map::{Map, Tile};
+//! # use game_lib::Position;
+//! use game_lib::actor::{Actor, Movement, TurnOptimal, WalkSampler};
+//! use game_lib::path::astar::AStar;
+//! use game_lib::path::spacetime::{Reservations, SpaceTime, Timed, TimedSampler};
+//! use game_lib::path::{Optimizer, PathResult, Target};
+//!
+//! let mut map = Map::new(5, 3);
+//! for x in 1..4 {
+//!     map[(x, 1)] = Tile::FLOOR;
+//! }
+//!
+//! // A guard stands in the corridor for the next two turns
+//! let mut reservations = Reservations::new();
+//! reservations.reserve(Position::new(2, 1), 1);
+//! reservations.reserve(Position::new(2, 1), 2);
+//!
+//! let mut turn_optimal = TurnOptimal::new(map);
+//! let mut model = SpaceTime::new(&mut turn_optimal, &reservations, 20);
+//! let mut sampler = TimedSampler::new(WalkSampler::with_wait());
+//! let start = Timed::new(Actor::new(1, 1, 0, 0), 0);
+//! let goal = Target::At(Position::new(3, 1));
+//!
+//! let result = AStar::new().optimize(&mut model, &start, &goal, &mut sampler);
+//! if let PathResult::Final(trajectory) = result {
+//!     let turns: Vec<_> = trajectory.trajectory.iter().map(|(s, _)| s.turn).collect();
+//!     assert_eq!(turns, vec![0, 1, 2, 3, 4]);
+//!     assert_eq!(trajectory.trajectory[1].1, Movement::Wait);
+//! } else {
+//!     panic!("the corridor is free after the guard leaves");
+//! }
+//! ```
+//!
+//! [`SpaceTime`]: struct.SpaceTime.html
+//! [`Model`]: ../trait.Model.html
+//! [`Reservations`]: struct.Reservations.html
+//! [`WalkSampler::with_wait`]: ../../actor/struct.WalkSampler.html#method.with_wait
+
+use super::{HeuristicModel, Model, PathFindingErr, Sampler, State, Trajectory};
+use crate::Position;
+use fnv::{FnvHashMap, FnvHashSet};
+
+use std::hash::Hash;
+
+/// Grid position of the states of a model
+type Pos<M> = <<M as Model>::State as State>::Position;
+
+/// A state together with the turn it is reached on
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Timed<S> {
+    pub state: S,
+    pub turn: usize,
+}
+
+impl<S> Timed<S> {
+    pub fn new(state: S, turn: usize) -> Self {
+        Timed { state, turn }
+    }
+}
+
+impl<S> State for Timed<S>
+where
+    S: State,
+{
+    type Position = (S::Position, usize);
+
+    fn grid_position(&self) -> Self::Position {
+        (self.state.grid_position(), self.turn)
+    }
+
+    fn tile(&self) -> Option<Position> {
+        self.state.tile()
+    }
+}
+
+/// Positions which are occupied on specific turns
+///
+/// Besides single positions, a table holds the moves between two positions, so that two
+/// entities cannot swap places by walking through each other, and positions which stay
+/// occupied from a turn onward, such as an entity which reached its destination.
+#[derive(Debug, Clone)]
+pub struct Reservations<P>
+where
+    P: Eq + Hash,
+{
+    vertices: FnvHashSet<(P, usize)>,
+    edges: FnvHashSet<(P, P, usize)>,
+    parked: FnvHashMap<P, usize>,
+    last: FnvHashMap<P, usize>,
+}
+
+impl<P> Default for Reservations<P>
+where
+    P: Eq + Hash,
+{
+    fn default() -> Self {
+        Reservations {
+            vertices: Default::default(),
+            edges: Default::default(),
+            parked: Default::default(),
+            last: Default::default(),
+        }
+    }
+}
+
+impl<P> Reservations<P>
+where
+    P: Eq + Hash + Clone,
+{
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+        self.edges.clear();
+        self.parked.clear();
+        self.last.clear();
+    }
+
+    /// Occupy a position on a single turn
+    pub fn reserve(&mut self, pos: P, turn: usize) {
+        let last = self.last.entry(pos.clone()).or_insert(turn);
+        *last = (*last).max(turn);
+        self.vertices.insert((pos, turn));
+    }
+
+    /// Occupy the move from one position to another, made between `turn` and the next
+    pub fn reserve_edge(&mut self, from: P, to: P, turn: usize) {
+        self.edges.insert((from, to, turn));
+    }
+
+    /// Occupy a position from a turn onward
+    pub fn reserve_from(&mut self, pos: P, turn: usize) {
+        let parked = self.parked.entry(pos).or_insert(turn);
+        *parked = (*parked).min(turn);
+    }
+
+    /// Occupy every position and move along a path which starts on `turn`
+    ///
+    /// The last position stays occupied after the path ends.
+    pub fn reserve_path<I>(&mut self, turn: usize, path: I)
+    where
+        I: IntoIterator<Item = P>,
+    {
+        let mut previous: Option<P> = None;
+        let mut turn = turn;
+
+        for pos in path {
+            if let Some(previous) = previous.take() {
+                self.reserve_edge(previous, pos.clone(), turn);
+                turn += 1;
+            }
+            self.reserve(pos.clone(), turn);
+            previous = Some(pos);
+        }
+
+        if let Some(last) = previous {
+            self.reserve_from(last, turn);
+        }
+    }
+
+    /// Test if a position is occupied on a turn
+    pub fn is_reserved(&self, pos: &P, turn: usize) -> bool {
+        self.parked.get(pos).map(|&from| from <= turn).unwrap_or(false)
+            || self.vertices.contains(&(pos.clone(), turn))
+    }
+
+    /// Test if moving from one position to another between `turn` and the next would
+    /// swap places with an entity moving the other way
+    pub fn is_swap(&self, from: &P, to: &P, turn: usize) -> bool {
+        self.edges.contains(&(to.clone(), from.clone(), turn))
+    }
+
+    /// Test if a position is occupied on any turn after `turn`
+    pub fn is_reserved_after(&self, pos: &P, turn: usize) -> bool {
+        self.parked.contains_key(pos)
+            || self.last.get(pos).map(|&last| last > turn).unwrap_or(false)
+    }
+}
+
+/// Extend a model with time, so that the plan avoids the positions reserved by others
+///
+/// A state may only be entered on a turn its position is free, and a goal is only reached
+/// once nobody else needs its position afterwards.  States past the horizon turn are not
+/// expanded, which bounds the search when no plan exists.
+#[derive(Debug)]
+pub struct SpaceTime<'a, M>
+where
+    M: Model,
+{
+    model: &'a mut M,
+    reservations: &'a Reservations<Pos<M>>,
+    horizon: usize,
+    windowed: bool,
+}
+
+impl<'a, M> SpaceTime<'a, M>
+where
+    M: Model,
+{
+    pub fn new(
+        model: &'a mut M,
+        reservations: &'a Reservations<Pos<M>>,
+        horizon: usize,
+    ) -> Self {
+        SpaceTime { model, reservations, horizon, windowed: false }
+    }
+
+    /// Treat reaching the horizon as reaching the goal
+    ///
+    /// The plan then ends on the horizon at the state which looks the closest to the goal,
+    /// unless the goal is reached sooner.  This is used to plan a window of a few turns at
+    /// a time, and plan again before the window runs out.
+    pub fn windowed(mut self) -> Self {
+        self.windowed = true;
+        self
+    }
+
+    pub fn model(&self) -> &M {
+        self.model
+    }
+
+    pub fn reservations(&self) -> &'a Reservations<Pos<M>> {
+        self.reservations
+    }
+
+    pub fn horizon(&self) -> usize {
+        self.horizon
+    }
+}
+
+impl<'a, M> Model for SpaceTime<'a, M>
+where
+    M: Model,
+    Pos<M>: Clone,
+{
+    type State = Timed<M::State>;
+    type Control = M::Control;
+    type Cost = M::Cost;
+    type Goal = M::Goal;
+
+    fn cost(
+        &self,
+        current: &Self::State,
+        control: &Self::Control,
+        next: &Self::State,
+    ) -> Self::Cost {
+        self.model.cost(&current.state, control, &next.state)
+    }
+
+    fn init(&mut self, initial: &Self::State) {
+        self.model.init(&initial.state)
+    }
+
+    fn on_expand(&mut self, state: &Self::State) {
+        self.model.on_expand(&state.state)
+    }
+
+    /// The trajectory is copied without the turns for the wrapped model
+    fn on_solution(&mut self, trajectory: &Trajectory<Self>) {
+        let untimed = Trajectory {
+            cost: trajectory.cost.clone(),
+            trajectory: trajectory
+                .trajectory
+                .iter()
+                .map(|(timed, control)| (timed.state.clone(), control.clone()))
+                .collect(),
+            goal: trajectory.goal,
+        };
+        self.model.on_solution(&untimed)
+    }
+
+    fn finish(&mut self) {
+        self.model.finish()
+    }
+
+    fn converge(&self, current: &Self::State, goal: &Self::Goal) -> bool {
+        if self.windowed && current.turn >= self.horizon {
+            return true;
+        }
+
+        self.model.converge(&current.state, goal)
+            && !self
+                .reservations
+                .is_reserved_after(&current.state.grid_position(), current.turn)
+    }
+
+    fn validate(&self, start: &Self::State, goal: &Self::Goal) -> Result<(), PathFindingErr> {
+        self.model.validate(&start.state, goal)
+    }
+
+    fn integrate(
+        &self,
+        previous: &Self::State,
+        control: &Self::Control,
+    ) -> Option<Self::State> {
+        if previous.turn >= self.horizon {
+            return None;
+        }
+
+        let next = self.model.integrate(&previous.state, control)?;
+        let (from, to) = (previous.state.grid_position(), next.grid_position());
+
+        if self.reservations.is_reserved(&to, previous.turn + 1)
+            || self.reservations.is_swap(&from, &to, previous.turn)
+        {
+            None
+        } else {
+            Some(Timed::new(next, previous.turn + 1))
+        }
+    }
+}
+
+impl<'a, M> HeuristicModel for SpaceTime<'a, M>
+where
+    M: HeuristicModel,
+    Pos<M>: Clone,
+{
+    fn heuristic(&self, current: &Self::State, goal: &Self::Goal) -> Self::Cost {
+        self.model.heuristic(&current.state, goal)
+    }
+}
+
+/// Sample the controls of a sampler for the model which was extended with time
+#[derive(Debug, Clone, Default)]
+pub struct TimedSampler<S> {
+    sampler: S,
+}
+
+impl<S> TimedSampler<S> {
+    pub fn new(sampler: S) -> Self {
+        TimedSampler { sampler }
+    }
+
+    pub fn into_inner(self) -> S {
+        self.sampler
+    }
+}
+
+impl<'a, M, S> Sampler<SpaceTime<'a, M>> for TimedSampler<S>
+where
+    M: Model,
+    Pos<M>: Clone,
+    S: Sampler<M>,
+{
+    #[inline]
+    fn sample(
+        &mut self,
+        model: &SpaceTime<'a, M>,
+        current: &Timed<M::State>,
+    ) -> &[M::Control] {
+        self.sampler.sample(model.model, &current.state)
+    }
+}
@@ -0,0 +1,274 @@
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+
+use super::{Diagnostics, PathFindingErr};
+use crate::map::{Components, Map};
+use crate::{Position, Rect};
+
+/// A goal on grid positions
+///
+/// A target describes the positions where planning may converge, without needing a whole
+/// state to compare against.
+///
+/// ```
+/// # use game_lib::{Position, Rect};
+/// use game_lib::path::Target;
+///
+/// let adjacent = Target::Within(Position::new(5, 5), 1);
+/// assert!(adjacent.contains(&Position::new(6, 4)));
+/// assert!(!adjacent.contains(&Position::new(7, 5)));
+///
+/// let room = Target::Inside(Rect::new((10, 10), 4, 4));
+/// assert!(room.contains(&Position::new(12, 11)));
+///
+/// let even = Target::predicate(|p| p.x % 2 == 0);
+/// assert!(even.contains(&Position::new(2, 3)));
+/// ```
+#[derive(Clone)]
+pub enum Target {
+    /// Reach exactly this position
+    At(Position),
+    /// Reach any position within `radius` tiles of a position
+    ///
+    /// The radius is measured in king moves, or the Chebyshev distance, so a radius of `1`
+    /// converges on any position adjacent to the center, including diagonals.
+    Within(Position, u32),
+    /// Reach any position inside of a rectangle, as defined by [`Rect::contains`]
+    ///
+    /// [`Rect::contains`]: ../struct.Rect.html#method.contains
+    Inside(Rect),
+    /// Reach any one of a set of positions
+    AnyOf(Vec<Position>),
+    /// Reach any position which satisfies a predicate
+    ///
+    /// Nothing is known about where the predicate holds, so no distance can be estimated.
+    Predicate(Arc<dyn Fn(&Position) -> bool + Send + Sync>),
+}
+
+impl Target {
+    /// Reach any position which satisfies a predicate
+    pub fn predicate<F>(predicate: F) -> Self
+    where
+        F: Fn(&Position) -> bool + Send + Sync + 'static,
+    {
+        Target::Predicate(Arc::new(predicate))
+    }
+
+    /// Test if a position satisfies the target
+    pub fn contains(&self, pos: &Position) -> bool {
+        use Target::*;
+
+        match self {
+            At(goal) => goal == pos,
+            Within(center, radius) => {
+                let dx = (i64::from(pos.x) - i64::from(center.x)).abs();
+                let dy = (i64::from(pos.y) - i64::from(center.y)).abs();
+                dx.max(dy) <= i64::from(*radius)
+            }
+            Inside(rect) => rect.contains(pos),
+            AnyOf(goals) => goals.contains(pos),
+            Predicate(predicate) => predicate(pos),
+        }
+    }
+
+    /// Estimate the distance to the target with a distance metric
+    ///
+    /// Regions are estimated by the distance to the position of the region nearest to `pos` in
+    /// each axis, so any metric which never decreases as either axis gets farther apart, such
+    /// as the manhattan or octile distance, stays optimistic.  A set of positions is estimated
+    /// by its nearest member.
+    ///
+    /// Returns `None` when there is nothing to estimate against, like an empty set or a
+    /// predicate.
+    ///
+    /// ```
+    /// # use game_lib::Position;
+    /// use game_lib::path::Target;
+    ///
+    /// let manhattan = |a: &Position, b: &Position| {
+    ///     (i64::from(a.x) - i64::from(b.x)).abs() + (i64::from(a.y) - i64::from(b.y)).abs()
+    /// };
+    ///
+    /// let near = Target::Within(Position::new(5, 5), 2);
+    /// assert_eq!(near.estimate(&Position::new(10, 4), manhattan), Some(3));
+    ///
+    /// // A radius which covers any map is already reached
+    /// let anywhere = Target::Within(Position::new(5, 5), u32::MAX);
+    /// assert_eq!(anywhere.estimate(&Position::new(40, 2), manhattan), Some(0));
+    /// ```
+    pub fn estimate<C, F>(&self, pos: &Position, metric: F) -> Option<C>
+    where
+        C: Ord,
+        F: Fn(&Position, &Position) -> C,
+    {
+        use Target::*;
+
+        match self {
+            At(goal) => Some(metric(pos, goal)),
+            Within(center, radius) => {
+                let left = center.x.saturating_sub(*radius);
+                let top = center.y.saturating_sub(*radius);
+                let nearest = Position::new(
+                    pos.x.max(left).min(center.x.saturating_add(*radius)),
+                    pos.y.max(top).min(center.y.saturating_add(*radius)),
+                );
+                Some(metric(pos, &nearest))
+            }
+            Inside(rect) => {
+                let bottom = rect.pos.y + rect.h.max(1) - 1;
+                let nearest = Position::new(
+                    pos.x.max(rect.pos.x).min(rect.pos.x + rect.w),
+                    pos.y.max(rect.pos.y).min(bottom),
+                );
+                Some(metric(pos, &nearest))
+            }
+            AnyOf(goals) => goals.iter().map(|goal| metric(pos, goal)).min(),
+            Predicate(_) => None,
+        }
+    }
+
+    /// Check that the target has a position which can be entered on a map
+    ///
+    /// Fails with [`GoalOutOfBounds`] when no position of the target lies on the map, and with
+    /// [`GoalBlocked`] when all of those which do are blocking.  Predicates are never rejected.
+    ///
+    /// ```
+    /// # use game_lib::map::{Map, Tile};
+    /// # use game_lib::Position;
+    /// use game_lib::path::{Diagnostics, PathFindingErr, Target};
+    ///
+    /// let mut map = Map::new(10, 10);
+    /// map[(5, 5)] = Tile::FLOOR;
+    /// let none = Diagnostics::default();
+    ///
+    /// assert_eq!(Target::Within(Position::new(4, 4), 1).validate(&map), Ok(()));
+    /// assert_eq!(
+    ///     Target::At(Position::new(4, 4)).validate(&map),
+    ///     Err(PathFindingErr::GoalBlocked(none.clone()))
+    /// );
+    /// assert_eq!(
+    ///     Target::At(Position::new(20, 4)).validate(&map),
+    ///     Err(PathFindingErr::GoalOutOfBounds(none))
+    /// );
+    /// ```
+    ///
+    /// [`GoalOutOfBounds`]: enum.PathFindingErr.html#variant.GoalOutOfBounds
+    /// [`GoalBlocked`]: enum.PathFindingErr.html#variant.GoalBlocked
+    pub fn validate(&self, map: &Map) -> Result<(), PathFindingErr> {
+        let (width, height) = map.dimensions();
+        let positions = match self.positions(width, height) {
+            Some(positions) => positions,
+            None => return Ok(()),
+        };
+
+        let mut on_map = false;
+        for pos in positions.filter(|pos| map.contains(pos)) {
+            if map.is_passable(&pos) {
+                return Ok(());
+            }
+            on_map = true;
+        }
+
+        if on_map {
+            Err(PathFindingErr::GoalBlocked(Diagnostics::default()))
+        } else {
+            Err(PathFindingErr::GoalOutOfBounds(Diagnostics::default()))
+        }
+    }
+
+    /// Test if a position of the target is in the same component as `start`
+    ///
+    /// Predicates are always considered connected.
+    pub fn is_connected(&self, components: &Components, start: &Position) -> bool {
+        let label = match components.label(start) {
+            Some(label) => label,
+            None => return false,
+        };
+
+        let (width, height) = components.dimensions();
+        match self.positions(width, height) {
+            Some(mut positions) => positions.any(|pos| components.label(&pos) == Some(label)),
+            None => true,
+        }
+    }
+
+    /// Check that the target is in the same component as `start`
+    ///
+    /// Fails with [`Unreachable`] when it is not, with the size of the component of `start` as
+    /// the closed region.  Models return the error from [`Model::validate`], which optimizers
+    /// ignore when their budget asks for best effort.
+    ///
+    /// [`Unreachable`]: enum.PathFindingErr.html#variant.Unreachable
+    /// [`Model::validate`]: trait.Model.html#method.validate
+    pub fn validate_connected(
+        &self,
+        components: &Components,
+        start: &Position,
+    ) -> Result<(), PathFindingErr> {
+        if self.is_connected(components, start) {
+            Ok(())
+        } else {
+            let closed = components.label(start).map(|label| components.size(label));
+            Err(PathFindingErr::Unreachable(Diagnostics {
+                closed: closed.unwrap_or(0),
+                ..Default::default()
+            }))
+        }
+    }
+
+    /// The positions of the target, with regions clipped to an area of `width` by `height`
+    ///
+    /// Returns `None` for predicates, which have no known positions.  Clipping keeps huge
+    /// regions cheap to check, but sets of positions are not clipped.
+    pub(crate) fn positions(
+        &self,
+        width: u32,
+        height: u32,
+    ) -> Option<Box<dyn Iterator<Item = Position> + '_>> {
+        use Target::*;
+
+        let (right, bottom) = (width.saturating_sub(1), height.saturating_sub(1));
+        let area = |left: u32, top: u32, right: u32, bottom: u32| {
+            (top..=bottom).flat_map(move |y| (left..=right).map(move |x| Position::new(x, y)))
+        };
+
+        Some(match self {
+            At(pos) => Box::new(std::iter::once(pos.clone())),
+            Within(center, radius) => Box::new(area(
+                center.x.saturating_sub(*radius),
+                center.y.saturating_sub(*radius),
+                center.x.saturating_add(*radius).min(right),
+                center.y.saturating_add(*radius).min(bottom),
+            )),
+            Inside(rect) if rect.h == 0 => Box::new(std::iter::empty()),
+            Inside(rect) => Box::new(area(
+                rect.pos.x,
+                rect.pos.y,
+                (rect.pos.x + rect.w).min(right),
+                (rect.pos.y + rect.h - 1).min(bottom),
+            )),
+            AnyOf(goals) => Box::new(goals.iter().cloned()),
+            Predicate(_) => return None,
+        })
+    }
+}
+
+impl From<Position> for Target {
+    fn from(pos: Position) -> Self {
+        Target::At(pos)
+    }
+}
+
+impl Debug for Target {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use Target::*;
+
+        match self {
+            At(pos) => f.debug_tuple("At").field(pos).finish(),
+            Within(pos, radius) => f.debug_tuple("Within").field(pos).field(radius).finish(),
+            Inside(rect) => f.debug_tuple("Inside").field(rect).finish(),
+            AnyOf(goals) => f.debug_tuple("AnyOf").field(goals).finish(),
+            Predicate(_) => f.debug_tuple("Predicate").finish(),
+        }
+    }
+}
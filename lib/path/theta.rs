@@ -0,0 +1,403 @@
+use std::fmt::{Debug, Formatter};
+
+use fnv::FnvHashMap;
+use std::cmp::Reverse;
+use std::collections::hash_map::Entry;
+use std::collections::BinaryHeap;
+
+use super::astar::estimate;
+use super::budget::{Budget, Progress};
+use super::*;
+
+/// Theta*, an A* search whose paths may turn at any angle
+///
+/// Whenever a new state is in sight of the parent of the state it was sampled from, the new
+/// state is linked directly to that parent, skipping the state in between.  The trajectory
+/// is then made of _waypoints_, each one in sight of the previous one, instead of a state for
+/// every control.  The paths stay straight across open areas, rather than zig-zagging
+/// between the few directions the sampler offers.
+///
+/// Each waypoint keeps the control of the last step which reached it, and the cost of the
+/// trajectory is the sum of the [`distance`]s between waypoints.  The steps between two
+/// waypoints must be recovered from the model, for example with [`nav::walk`] for a map.
+///
+/// Plain Theta* tests the line of sight for every child it samples, although most of them are
+/// never expanded.  [`ThetaStar::lazy`] builds the Lazy Theta* variant instead, which tests
+/// it only once a state is expanded.
+///
+/// [`distance`]: ../trait.LineOfSight.html#tymethod.distance
+/// [`nav::walk`]: ../../nav/fn.walk.html
+/// [`ThetaStar::lazy`]: #method.lazy
+pub struct ThetaStar<M>
+where
+    M: LineOfSight,
+    M::Cost: Copy,
+{
+    queue: BinaryHeap<Reverse<(M::Cost, usize)>>,
+    nodes: Vec<Node<M>>,
+    grid: FnvHashMap<<<M as Model>::State as State>::Position, M::Cost>,
+    budget: Budget<M>,
+    lazy: bool,
+    searching: bool,
+}
+
+struct Node<M>
+where
+    M: Model,
+{
+    state: M::State,
+    control: M::Control,
+    g: M::Cost,
+    parent: Option<usize>,
+    /// The node the state was sampled from, and the cost of the control from it
+    via: Option<(usize, M::Cost)>,
+}
+
+impl<M> ThetaStar<M>
+where
+    M: LineOfSight,
+    M::Cost: Copy,
+{
+    pub fn new() -> Self {
+        ThetaStar {
+            queue: BinaryHeap::new(),
+            nodes: Vec::new(),
+            grid: FnvHashMap::default(),
+            budget: Budget::unlimited(),
+            lazy: false,
+            searching: false,
+        }
+    }
+
+    /// Lazy Theta*, which assumes every new state is in sight of its grandparent
+    ///
+    /// The line of sight is only tested when a state is about to be expanded.  When it is
+    /// blocked, the state falls back to the state it was sampled from, at the cost of the
+    /// control between them.  This saves a Bresenham walk for each of the many children which
+    /// are queued but never expanded, at the price of paths which may be slightly longer than
+    /// the ones of [`ThetaStar::new`], since only the state sampled from is considered as a
+    /// fallback parent.
+    ///
+    /// ```
+    /// # use game_lib::map::{Map, Tile};
+    /// # use game_lib::Position;
+    /// use game_lib::nav::{Grid, Octile};
+    /// use game_lib::path::theta::ThetaStar;
+    /// use game_lib::path::{LineOfSight, Optimizer, PathResult, Target};
+    ///
+    /// let mut map = Map::new(12, 8);
+    /// for y in 1..7 {
+    ///     for x in 1..11 {
+    ///         map[(x, y)] = Tile::FLOOR;
+    ///     }
+    /// }
+    /// // A pillar in the middle of the room
+    /// for y in 2..6 {
+    ///     map[(5, y)] = Tile::WALL;
+    /// }
+    ///
+    /// let mut grid = Grid::new(&map);
+    /// let start = Position::new(2, 4);
+    /// let goal = Target::At(Position::new(9, 3));
+    /// let waypoints: Vec<Position> =
+    ///     match ThetaStar::lazy().optimize(&mut grid, &start, &goal, &mut Octile) {
+    ///         PathResult::Final(trajectory) => {
+    ///             trajectory.trajectory.into_iter().map(|s| s.0).collect()
+    ///         }
+    ///         _ => unreachable!(),
+    ///     };
+    ///
+    /// // Around the pillar, with every waypoint in sight of the previous one
+    /// assert!(waypoints.len() > 2);
+    /// assert!(waypoints.windows(2).all(|pair| grid.line_of_sight(&pair[0], &pair[1])));
+    /// ```
+    ///
+    /// [`ThetaStar::new`]: #method.new
+    pub fn lazy() -> Self {
+        ThetaStar { lazy: true, ..Self::new() }
+    }
+
+    pub fn clear(&mut self) {
+        self.queue.clear();
+        self.nodes.clear();
+        self.grid.clear();
+        self.searching = false;
+    }
+
+    pub fn budget(&self) -> &Budget<M> {
+        &self.budget
+    }
+
+    /// Limit the work of the following searches, and what they return when giving up
+    pub fn set_budget(&mut self, budget: Budget<M>) {
+        self.budget = budget
+    }
+
+    pub fn inspect_queue(&self) -> impl Iterator<Item = (&M::State, &M::Control)> {
+        self.queue
+            .iter()
+            .map(move |Reverse((_, i))| (&self.nodes[*i].state, &self.nodes[*i].control))
+    }
+
+    pub fn inspect_discovered(
+        &self,
+    ) -> impl Iterator<Item = &<<M as Model>::State as State>::Position> {
+        self.grid.keys()
+    }
+
+    fn start(&mut self, model: &M, start: &M::State, goals: &[M::Goal]) {
+        let g = M::Cost::default();
+        self.grid.insert(start.grid_position(), g);
+        self.queue.push(Reverse((g + estimate(model, start, goals), self.nodes.len())));
+        self.nodes.push(Node {
+            state: start.clone(),
+            control: Default::default(),
+            g,
+            parent: None,
+            via: None,
+        });
+    }
+
+    /// The next node of the queue which was not reached more cheaply since it was queued
+    fn pop(&mut self, model: &M) -> Option<usize> {
+        while let Some(Reverse((_, current))) = self.queue.pop() {
+            let node = &self.nodes[current];
+            if self.grid.get(&node.state.grid_position()) == Some(&node.g) {
+                if self.lazy {
+                    self.verify(model, current);
+                }
+                return Some(current);
+            }
+        }
+
+        None
+    }
+
+    /// Link a node back to the state it was sampled from when its parent is out of sight
+    fn verify(&mut self, model: &M, current: usize) {
+        let node = &self.nodes[current];
+        let (via, cost) = match (node.parent, node.via) {
+            (Some(parent), Some((via, cost))) if parent != via => {
+                if model.line_of_sight(&self.nodes[parent].state, &node.state) {
+                    return;
+                }
+                (via, cost)
+            }
+            _ => return,
+        };
+
+        let g = self.nodes[via].g + cost;
+        self.grid.insert(node.state.grid_position(), g);
+        let node = &mut self.nodes[current];
+        node.parent = Some(via);
+        node.g = g;
+    }
+
+    #[inline(always)]
+    fn step<S>(
+        &mut self,
+        current: usize,
+        model: &mut M,
+        goals: &[M::Goal],
+        sampler: &mut S,
+    ) -> Option<usize>
+    where
+        S: Sampler<M>,
+    {
+        let state = self.nodes[current].state.clone();
+        if let Some(reached) = goals.iter().position(|goal| model.converge(&state, goal)) {
+            return Some(reached);
+        }
+
+        model.on_expand(&state);
+        for control in sampler.sample(model, &state) {
+            if let Some(child_state) = model.integrate(&state, control) {
+                // Skip the current state when its parent can see the child, which the lazy
+                // variant assumes until the child is expanded
+                let cost = model.cost(&state, control, &child_state);
+                let (parent, g) = match self.nodes[current].parent {
+                    Some(grandparent)
+                        if self.lazy
+                            || model.line_of_sight(
+                                &self.nodes[grandparent].state,
+                                &child_state,
+                            ) =>
+                    {
+                        let grandparent_node = &self.nodes[grandparent];
+                        let distance = model.distance(&grandparent_node.state, &child_state);
+                        (grandparent, grandparent_node.g + distance)
+                    }
+                    _ => (current, self.nodes[current].g + cost),
+                };
+
+                match self.grid.entry(child_state.grid_position()) {
+                    Entry::Occupied(mut best) => {
+                        if *best.get() <= g {
+                            continue;
+                        }
+                        best.insert(g);
+                    }
+                    Entry::Vacant(empty) => {
+                        empty.insert(g);
+                    }
+                }
+
+                let f = g + estimate(model, &child_state, goals);
+                self.queue.push(Reverse((f, self.nodes.len())));
+                self.nodes.push(Node {
+                    state: child_state,
+                    control: control.clone(),
+                    g,
+                    parent: Some(parent),
+                    via: Some((current, cost)),
+                });
+            }
+        }
+
+        None
+    }
+
+    /// Follow the parents from a node up to the start node
+    fn unwind_trajectory(&self, mut current: usize, goal: usize) -> Trajectory<M> {
+        let cost = self.nodes[current].g;
+        let mut result =
+            vec![(self.nodes[current].state.clone(), self.nodes[current].control.clone())];
+
+        while let Some(parent) = self.nodes[current].parent {
+            current = parent;
+            result.push((
+                self.nodes[current].state.clone(),
+                self.nodes[current].control.clone(),
+            ));
+        }
+
+        result.reverse();
+
+        Trajectory { cost, trajectory: result, goal }
+    }
+}
+
+impl<M, S> Optimizer<M, S> for ThetaStar<M>
+where
+    M: LineOfSight,
+    M::Cost: Copy,
+    S: Sampler<M>,
+{
+    fn clear(&mut self) {
+        ThetaStar::clear(self)
+    }
+
+    fn next_trajectory(
+        &mut self,
+        model: &mut M,
+        start: &M::State,
+        goal: &M::Goal,
+        sampler: &mut S,
+    ) -> PathResult<M> {
+        use PathFindingErr::*;
+        use PathResult::*;
+
+        let goals = std::slice::from_ref(goal);
+        if !self.searching {
+            self.clear();
+            if let Some(err) = validate(model, start, goals, &self.budget).err() {
+                return Err(err);
+            }
+
+            model.init(start);
+            self.start(model, start, goals);
+            self.searching = true;
+        }
+
+        let result = if let Some(current) = self.pop(model) {
+            match self.step(current, model, goals, sampler) {
+                Some(reached) => Final(self.unwind_trajectory(current, reached)),
+                None => Intermediate(self.unwind_trajectory(current, 0)),
+            }
+        } else {
+            Err(Unreachable(Diagnostics { closed: self.grid.len(), ..Default::default() }))
+        };
+
+        conclude(model, result, &mut self.searching)
+    }
+
+    fn optimize_nearest(
+        &mut self,
+        model: &mut M,
+        start: &M::State,
+        goals: &[M::Goal],
+        sampler: &mut S,
+    ) -> PathResult<M> {
+        use PathResult::*;
+
+        if !self.searching {
+            self.clear();
+            if let Some(reached) = goals.iter().position(|goal| model.converge(start, goal)) {
+                model.init(start);
+                return conclude(
+                    model,
+                    Final(Trajectory {
+                        cost: Default::default(),
+                        trajectory: vec![(start.clone(), Default::default())],
+                        goal: reached,
+                    }),
+                    &mut self.searching,
+                );
+            }
+
+            if let Some(err) = validate(model, start, goals, &self.budget).err() {
+                return Err(err);
+            }
+
+            model.init(start);
+            self.start(model, start, goals);
+            self.searching = true;
+        }
+
+        let mut progress = Progress::new();
+
+        while let Some(current) = self.pop(model) {
+            if let Some(reached) = self.step(current, model, goals, sampler) {
+                let result = Final(self.unwind_trajectory(current, reached));
+                return conclude(model, result, &mut self.searching);
+            }
+
+            if !progress
+                .expand(&self.budget, model, &self.nodes[current].state, goals, || current)
+            {
+                break;
+            }
+        }
+
+        let result = progress
+            .finish(self.grid.len())
+            .map_or_else(Err, |(node, goal)| Partial(self.unwind_trajectory(node, goal)));
+
+        conclude(model, result, &mut self.searching)
+    }
+}
+
+impl<M> Debug for ThetaStar<M>
+where
+    M: LineOfSight,
+    M::Cost: Copy,
+{
+    fn fmt(&self, f: &mut Formatter) -> std::fmt::Result {
+        f.debug_struct("ThetaStar")
+            .field("queue", &self.queue.len())
+            .field("nodes", &self.nodes.len())
+            .field("grid", &self.grid.len())
+            .field("lazy", &self.lazy)
+            .finish()
+    }
+}
+
+impl<M> Default for ThetaStar<M>
+where
+    M: LineOfSight,
+    M::Cost: Copy,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,6 +1,7 @@
-use game_lib::actor::{Actor, Heuristic, TeleportSampler, TurnOptimal, WalkSampler};
+use game_lib::actor::{Actor, Heuristic, MoveSet, TeleportSampler, TurnOptimal, WalkSampler};
 use game_lib::map::{generate, Map, Tile};
-use game_lib::path::{Algorithm, Optimizer, PathResult, Trajectory};
+use game_lib::nav::DistanceField;
+use game_lib::path::{Algorithm, Optimizer, PathResult, Target, Trajectory};
 use game_lib::Position as Pos;
 
 use rand::thread_rng;
@@ -10,6 +11,8 @@ use tcod::input::{self, Event, Key, Mouse};
 use tui::style::{Color, Style};
 use tui::Terminal;
 
+use std::sync::Arc;
+
 mod ui;
 
 /// Screen width in number of vertical columns of text
@@ -34,6 +37,10 @@ const COLOR_GROUND_BG: Color = Color::Rgb(246, 230, 206);
 const COLOR_MONSTER: Color = Color::Rgb(44, 200, 247);
 const COLOR_PLAYER: Color = Color::Rgb(188, 7, 98);
 
+// Gradient of the distance field overlay
+const COLOR_FIELD_NEAR: Color = Color::Rgb(120, 200, 140);
+const COLOR_FIELD_FAR: Color = Color::Rgb(246, 230, 206);
+
 use crate::ui::widgets::Visualization;
 
 struct Settings {
@@ -56,24 +63,62 @@ impl Sampler {
     }
 }
 
+/// The heuristic family to plan with, where the move set follows the sampler
+#[derive(Clone, Copy)]
+enum HeuristicKind {
+    Diagonal,
+    Euclidean,
+    Moves,
+}
+
+impl HeuristicKind {
+    fn toggle(&mut self) {
+        use HeuristicKind::*;
+        *self = match self {
+            Diagonal => Euclidean,
+            Euclidean => Moves,
+            Moves => Diagonal,
+        }
+    }
+
+    fn heuristic(self, sampler: &Sampler) -> Heuristic {
+        match (self, sampler) {
+            (HeuristicKind::Diagonal, _) => Heuristic::Diagonal,
+            (HeuristicKind::Euclidean, _) => Heuristic::Euclidean,
+            (HeuristicKind::Moves, Sampler::Walk) => Heuristic::Moves(MoveSet::walk()),
+            (HeuristicKind::Moves, Sampler::Teleport) => Heuristic::Moves(MoveSet::teleport()),
+        }
+    }
+}
+
 struct App {
     pub map_pos: Pos,
-    pub map: Map,
+    pub map: Arc<Map>,
+    pub model: TurnOptimal,
     pub sampler: Sampler,
+    pub heuristic: HeuristicKind,
     pub settings: Settings,
     pub monster: Option<Actor>,
     pub player: Option<Actor>,
     pub algorithm: Algorithm<TurnOptimal>,
     pub trajectory: PathResult<TurnOptimal>,
+    pub show_field: bool,
+    pub field: Option<DistanceField>,
 }
 
 impl Default for App {
     fn default() -> Self {
         let mut map_rng = thread_rng();
+        let map = Arc::new(generate(&mut map_rng, MAP_WIDTH, MAP_HEIGHT));
+        let (sampler, heuristic) = (Sampler::Walk, HeuristicKind::Diagonal);
+        let mut model = TurnOptimal::new(Arc::clone(&map));
+        model.set_heuristic(heuristic.heuristic(&sampler));
         App {
             map_pos: Pos::zero(),
-            map: generate(&mut map_rng, MAP_WIDTH, MAP_HEIGHT),
-            sampler: Sampler::Walk,
+            model,
+            map,
+            sampler,
+            heuristic,
             settings: Settings {
                 items: vec![
                     ("Re-Generate Map".to_string(), &|a| {
@@ -81,7 +126,8 @@ impl Default for App {
                         a.clear();
                         a.player = None;
                         a.monster = None;
-                        a.map = generate(&mut rng, MAP_WIDTH, MAP_HEIGHT);
+                        a.map = Arc::new(generate(&mut rng, MAP_WIDTH, MAP_HEIGHT));
+                        a.field = None;
                     }),
                     ("Switch Optimizer [A*]".to_string(), &|a| {
                         a.clear();
@@ -90,18 +136,33 @@ impl Default for App {
                             Algorithm::Dijkstra(_) => "Dijkstra",
                             Algorithm::AStar(_) => "A*",
                             Algorithm::OptimalAStar(_) => "High Performance A*",
+                            Algorithm::Beam(_) => "Beam",
                         };
                         a.settings.items[1].0 = format!("Switch Optimizer [{}]", name);
                     }),
                     ("Switch Sampler [Walk]".to_string(), &|a| {
                         a.clear();
                         a.sampler.toggle();
+                        a.update_heuristic();
                         let name = match a.sampler {
                             Sampler::Walk => "Walk",
                             Sampler::Teleport => "Teleport",
                         };
                         a.settings.items[2].0 = format!("Switch Sampler [{}]", name);
                     }),
+                    ("Distance Field [Off]".to_string(), &|a| {
+                        a.show_field = !a.show_field;
+                        a.update_field();
+                        let name = if a.show_field { "On" } else { "Off" };
+                        a.settings.items[3].0 = format!("Distance Field [{}]", name);
+                    }),
+                    ("Switch Heuristic [Diagonal]".to_string(), &|a| {
+                        a.clear();
+                        a.heuristic.toggle();
+                        a.update_heuristic();
+                        let name = a.heuristic.heuristic(&a.sampler).to_string();
+                        a.settings.items[4].0 = format!("Switch Heuristic [{}]", name);
+                    }),
                 ],
                 selected: 0,
             },
@@ -109,6 +170,8 @@ impl Default for App {
             player: None,
             algorithm: Algorithm::default(),
             trajectory: PathResult::Intermediate(Trajectory::default()),
+            show_field: false,
+            field: None,
         }
     }
 }
@@ -126,9 +189,20 @@ impl App {
         {
             self.clear();
             self.player = player;
+            self.update_field();
         }
     }
 
+    /// Flood the map from the player when the distance field overlay is shown
+    pub fn update_field(&mut self) {
+        self.field = match &self.player {
+            Some(player) if self.show_field => {
+                Some(DistanceField::new(&self.map, &[(player.pos.clone(), 0)]))
+            }
+            _ => None,
+        };
+    }
+
     pub fn update_monster(&mut self, monster: Option<Actor>) {
         if monster
             .as_ref()
@@ -140,6 +214,20 @@ impl App {
         }
     }
 
+    /// Keep the model on the revision of the map shown, restarting the plan when it changed
+    fn update_model(&mut self) {
+        if !self.model.is_current(&self.map) {
+            self.model.set_map(Arc::clone(&self.map));
+            self.update_heuristic();
+            self.clear();
+        }
+    }
+
+    /// Estimate with the heuristic chosen for the current sampler
+    fn update_heuristic(&mut self) {
+        self.model.set_heuristic(self.heuristic.heuristic(&self.sampler));
+    }
+
     pub fn clear(&mut self) {
         self.algorithm.clear();
         self.trajectory = PathResult::Intermediate(Trajectory::default());
@@ -149,6 +237,7 @@ impl App {
         match &self.trajectory {
             PathResult::Intermediate(t) => t.clone(),
             PathResult::Final(t) => t.clone(),
+            PathResult::Partial(t) => t.clone(),
             _ => Trajectory::default(),
         }
     }
@@ -167,16 +256,16 @@ impl App {
     }
 
     pub fn step(mut self) -> Self {
+        self.update_model();
         if let (Some(ref player), Some(ref monster)) = (&self.player, &self.monster) {
             if let PathResult::Intermediate(_) = &self.trajectory {
-                let mut model = TurnOptimal::new(self.map);
-                model.set_heuristic(Heuristic::Diagonal);
-                let goal = player.clone();
+                let model = &mut self.model;
+                let goal = Target::Within(player.pos.clone(), 1);
                 match self.sampler {
                     Sampler::Walk => {
                         let mut sampler = WalkSampler::new();
                         self.trajectory = self.algorithm.next_trajectory(
-                            &mut model,
+                            model,
                             &monster,
                             &goal,
                             &mut sampler,
@@ -185,14 +274,13 @@ impl App {
                     Sampler::Teleport => {
                         let mut sampler = TeleportSampler::new();
                         self.trajectory = self.algorithm.next_trajectory(
-                            &mut model,
+                            model,
                             &monster,
                             &goal,
                             &mut sampler,
                         );
                     }
                 };
-                self.map = model.return_map();
             }
         }
 
@@ -200,24 +288,23 @@ impl App {
     }
 
     pub fn complete_plan(mut self) -> Self {
+        self.update_model();
         if let (Some(ref player), Some(ref monster)) = (&self.player, &self.monster) {
             if let PathResult::Intermediate(_) = &self.trajectory {
-                let mut model = TurnOptimal::new(self.map);
-                model.set_heuristic(Heuristic::Diagonal);
-                let goal = player.clone();
+                let model = &mut self.model;
+                let goal = Target::Within(player.pos.clone(), 1);
                 match self.sampler {
                     Sampler::Walk => {
                         let mut sampler = WalkSampler::new();
                         self.trajectory =
-                            self.algorithm.optimize(&mut model, &monster, &goal, &mut sampler);
+                            self.algorithm.optimize(model, &monster, &goal, &mut sampler);
                     }
                     Sampler::Teleport => {
                         let mut sampler = TeleportSampler::new();
                         self.trajectory =
-                            self.algorithm.optimize(&mut model, &monster, &goal, &mut sampler);
+                            self.algorithm.optimize(model, &monster, &goal, &mut sampler);
                     }
                 };
-                self.map = model.return_map();
             }
         }
 
@@ -346,6 +433,11 @@ fn main() {
                     .queue_style(Style::default().fg(Color::Green).bg(COLOR_GROUND_BG))
                     .visualization(app.visualization());
 
+                if let Some(field) = &app.field {
+                    map_view =
+                        map_view.distance_field(field, COLOR_FIELD_NEAR, COLOR_FIELD_FAR);
+                }
+
                 if let Some(player) = &app.player {
                     map_view = map_view.player(
                         player.clone(),
@@ -1,11 +1,12 @@
 use game_lib::actor::Actor;
 use game_lib::map::{Map, Tile};
+use game_lib::nav::DistanceField;
 use game_lib::Position;
 use game_lib::Rect as Area;
 
 use tui::buffer::Buffer;
 use tui::layout::Rect;
-use tui::style::Style;
+use tui::style::{Color, Style};
 use tui::widgets::{Block, Widget};
 
 use std::collections::{HashMap, HashSet};
@@ -19,6 +20,7 @@ where
     map_pos: Position,
     block: Option<Block<'a>>,
     visualization: Option<Visualization>,
+    field: Option<(&'a DistanceField, Color, Color)>,
     style_fn: F,
     mouse_position: Option<Position>,
     mouse_callback: Option<M>,
@@ -41,6 +43,7 @@ where
             block: None,
             map_pos: Position::zero(),
             visualization: None,
+            field: None,
             mouse_position: None,
             mouse_callback: None,
             player: None,
@@ -66,6 +69,17 @@ where
         self
     }
 
+    /// Shade the floor by a distance field, blending from the `near` to the `far` color
+    pub fn distance_field(
+        mut self,
+        field: &'a DistanceField,
+        near: Color,
+        far: Color,
+    ) -> Self {
+        self.field = Some((field, near, far));
+        self
+    }
+
     pub fn player(mut self, p: Actor, s: &'a str, st: Style) -> Self {
         self.player = Some((p, s, st));
         self
@@ -147,6 +161,29 @@ where
             }
         }
 
+        if let Some((field, near, far)) = &self.field {
+            let max = field.max().unwrap_or(0).max(1);
+            legend_entries.push((
+                " ".to_string(),
+                Style::default().bg(*near),
+                "near".to_string(),
+            ));
+            legend_entries.push((
+                " ".to_string(),
+                Style::default().bg(*far),
+                "far".to_string(),
+            ));
+            for (pos, cost) in field.iter() {
+                if let Some(Position { x, y }) = map_coord
+                    .transform_to_local(&pos)
+                    .and_then(|pos| screen_coord.transform(&pos))
+                {
+                    let shade = blend(*near, *far, cost as f64 / max as f64);
+                    buf.get_mut(x as u16, y as u16).set_bg(shade);
+                }
+            }
+        }
+
         if let Some(Visualization { queue, visited, trajectory }) = &self.visualization {
             use tui::symbols;
             let style = &self.visited_style.unwrap_or_default();
@@ -267,6 +304,20 @@ where
     }
 }
 
+/// Linearly interpolate between two RGB colors
+///
+/// Colors which are not RGB are not blended, and the nearest of the two is used instead.
+fn blend(from: Color, to: Color, t: f64) -> Color {
+    match (from, to) {
+        (Color::Rgb(r1, g1, b1), Color::Rgb(r2, g2, b2)) => {
+            let lerp = |a: u8, b: u8| (f64::from(a) + (f64::from(b) - f64::from(a)) * t) as u8;
+            Color::Rgb(lerp(r1, r2), lerp(g1, g2), lerp(b1, b2))
+        }
+        _ if t < 0.5 => from,
+        _ => to,
+    }
+}
+
 pub struct Visualization {
     pub queue: HashMap<Position, usize>,
     pub visited: HashSet<Position>,